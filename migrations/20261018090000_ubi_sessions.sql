@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS ubi_sessions (
+    account VARCHAR(255) PRIMARY KEY,
+    ticket TEXT NOT NULL,
+    session_id VARCHAR(64) NOT NULL,
+    expiration VARCHAR(64) NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE ubi_sessions ADD COLUMN remember_me_ticket TEXT;
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Existing rows were all looked up as uplay profiles
+ALTER TABLE user_names ADD COLUMN platform VARCHAR(16) NOT NULL DEFAULT 'uplay';
+
+CREATE INDEX IF NOT EXISTS idx_user_names_platform_name ON user_names (platform, name);
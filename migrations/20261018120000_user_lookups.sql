@@ -0,0 +1,4 @@
+-- Add migration script here
+-- How often and how recently each profile was looked up, to rank search suggestions
+ALTER TABLE user_ids ADD COLUMN lookups INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE user_ids ADD COLUMN last_seen TIMESTAMP;
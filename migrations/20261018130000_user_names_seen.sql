@@ -0,0 +1,21 @@
+-- Add migration script here
+-- `ts` only held the first sighting of a name; keep both ends and a count.
+-- SQLite can't add columns defaulting to CURRENT_TIMESTAMP, so the table is rebuilt.
+CREATE TABLE user_names_seen (
+    user_id VARCHAR(64) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    platform VARCHAR(16) NOT NULL DEFAULT 'uplay',
+    first_seen TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sightings INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (user_id, name),
+    FOREIGN KEY (user_id) REFERENCES user_ids(id)
+);
+
+INSERT INTO user_names_seen (user_id, name, platform, first_seen, last_seen, sightings)
+SELECT user_id, name, platform, ts, ts, 1 FROM user_names;
+
+DROP TABLE user_names;
+ALTER TABLE user_names_seen RENAME TO user_names;
+
+CREATE INDEX IF NOT EXISTS idx_user_names_platform_name ON user_names (platform, name);
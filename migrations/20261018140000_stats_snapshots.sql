@@ -0,0 +1,17 @@
+-- Add migration script here
+-- Stats of every successful lookup, to show progress over time. A fetch identical
+-- to the previous snapshot of the same player, game and source only moves
+-- `last_fetched_at`, so refreshing doesn't add rows.
+CREATE TABLE IF NOT EXISTS stats_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id VARCHAR(64) NOT NULL,
+    game VARCHAR(64) NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    stats TEXT NOT NULL,
+    content_hash CHAR(64) NOT NULL,
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES user_ids(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stats_snapshots_user_game ON stats_snapshots (user_id, game, fetched_at);
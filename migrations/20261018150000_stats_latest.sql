@@ -0,0 +1,35 @@
+-- Add migration script here
+-- Latest known value of every numeric stat per player and game, kept next to the
+-- snapshots so leaderboards are an index scan instead of parsing JSON.
+CREATE TABLE IF NOT EXISTS stats_latest (
+    user_id VARCHAR(64) NOT NULL,
+    game VARCHAR(64) NOT NULL,
+    field VARCHAR(128) NOT NULL,
+    value REAL NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, game, field),
+    FOREIGN KEY (user_id) REFERENCES user_ids(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stats_latest_board ON stats_latest (game, field, value DESC);
+
+INSERT INTO stats_latest (user_id, game, field, value, source, updated_at)
+SELECT s.user_id, s.game, j.key, j.value, s.source, s.last_fetched_at
+FROM stats_snapshots s, json_each(s.stats) j
+WHERE j.type IN ('integer', 'real')
+    AND s.id = (
+        SELECT id FROM stats_snapshots l
+        WHERE l.user_id = s.user_id AND l.game = s.game
+        ORDER BY l.last_fetched_at DESC, l.id DESC LIMIT 1
+    );
+
+INSERT INTO stats_latest (user_id, game, field, value, source, updated_at)
+SELECT s.user_id, s.game, 'extra.' || j.key, j.value, s.source, s.last_fetched_at
+FROM stats_snapshots s, json_each(s.stats, '$.extra') j
+WHERE j.type IN ('integer', 'real')
+    AND s.id = (
+        SELECT id FROM stats_snapshots l
+        WHERE l.user_id = s.user_id AND l.game = s.game
+        ORDER BY l.last_fetched_at DESC, l.id DESC LIMIT 1
+    );
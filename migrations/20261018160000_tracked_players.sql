@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Players refreshed in the background so their history keeps growing without
+-- anyone looking them up. `last_*` hold the outcome of the latest refresh.
+CREATE TABLE IF NOT EXISTS tracked_players (
+    user_id VARCHAR(64) NOT NULL,
+    game VARCHAR(64) NOT NULL,
+    added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_refresh_at TIMESTAMP,
+    last_status_code INTEGER,
+    last_error_code VARCHAR(64),
+    last_message TEXT,
+    PRIMARY KEY (user_id, game),
+    FOREIGN KEY (user_id) REFERENCES user_ids(id)
+);
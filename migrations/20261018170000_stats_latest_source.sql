@@ -0,0 +1,44 @@
+-- Add migration script here
+-- Keep the latest values of every source apart, so a lookup from one source no
+-- longer overwrites what another one reported. Rebuilt from the latest snapshot
+-- of each player, game and source. Division 2 statscard snapshots stored before
+-- tracker-only fields were left out had them as 0, which isn't carried over.
+DROP TABLE IF EXISTS stats_latest;
+
+CREATE TABLE stats_latest (
+    user_id VARCHAR(64) NOT NULL,
+    game VARCHAR(64) NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    field VARCHAR(128) NOT NULL,
+    value REAL NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, game, source, field),
+    FOREIGN KEY (user_id) REFERENCES user_ids(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stats_latest_board ON stats_latest (game, field, value DESC);
+
+INSERT INTO stats_latest (user_id, game, source, field, value, updated_at)
+SELECT s.user_id, s.game, s.source, j.key, j.value, s.last_fetched_at
+FROM stats_snapshots s, json_each(s.stats) j
+WHERE j.type IN ('integer', 'real')
+    AND s.id = (
+        SELECT id FROM stats_snapshots l
+        WHERE l.user_id = s.user_id AND l.game = s.game AND l.source = s.source
+        ORDER BY l.last_fetched_at DESC, l.id DESC LIMIT 1
+    )
+    AND NOT (s.game = 'div2' AND s.source = 'ubi' AND j.key IN (
+        'shotgun_kills', 'smg_kills', 'pistol_kills', 'rifle_kills', 'pve_xp', 'pvp_xp', 'clan_xp',
+        'sharpshooter_kills', 'survivalist_kills', 'demolitionist_kills', 'commendation_count',
+        'rogue_playtime', 'longest_rogue'
+    ));
+
+INSERT INTO stats_latest (user_id, game, source, field, value, updated_at)
+SELECT s.user_id, s.game, s.source, 'extra.' || j.key, j.value, s.last_fetched_at
+FROM stats_snapshots s, json_each(s.stats, '$.extra') j
+WHERE j.type IN ('integer', 'real')
+    AND s.id = (
+        SELECT id FROM stats_snapshots l
+        WHERE l.user_id = s.user_id AND l.game = s.game AND l.source = s.source
+        ORDER BY l.last_fetched_at DESC, l.id DESC LIMIT 1
+    );
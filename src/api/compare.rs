@@ -0,0 +1,364 @@
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use serde_json::{Map, Value};
+use sqlx::{Pool, Sqlite};
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::history;
+use crate::api::progress::number;
+use crate::api::session::UbiSessionPool;
+use crate::api::ubi;
+use crate::config::AppConfig;
+use crate::db::snapshot::get_latest_snapshot;
+use crate::model::derived::percent;
+use crate::model::compare::{CompareDTO, CompareFieldDTO, ComparePlayerDTO};
+use crate::model::div::{D1PlayerStats, D2PlayerStats, Div2Source, Division};
+use crate::model::response::{ApiStatus, ResponseWithStatus};
+use crate::model::ubi::{Platform, ProfileDTO};
+
+pub const MIN_COMPARE_PLAYERS: usize = 2;
+pub const MAX_COMPARE_PLAYERS: usize = 10;
+
+/// A compared player and the stats they are compared with, as snapshotted.
+struct Compared {
+    profile: ProfileDTO,
+    source: String,
+    cached: bool,
+    fetched_at: String,
+    stats: Map<String, Value>,
+}
+
+/// The comma separated `names` of the compare routes, names or profile ids.
+pub fn parse_names(names: Option<&str>) -> UbiResult<Vec<String>> {
+    let names: Vec<String> = names
+        .unwrap_or_default()
+        .split(',')
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect();
+    if names.len() < MIN_COMPARE_PLAYERS || names.len() > MAX_COMPARE_PLAYERS {
+        return Err(UbiError::InvalidRequest(format!(
+            "names must list {} to {} players separated by commas",
+            MIN_COMPARE_PLAYERS, MAX_COMPARE_PLAYERS
+        )));
+    }
+    Ok(names)
+}
+
+/// The latest snapshot of `query` if it was fetched less than `compare_cache_secs` ago.
+async fn cached_stats(
+    pool: &Pool<Sqlite>,
+    config: &AppConfig,
+    division: Division,
+    platform: Platform,
+    query: &str,
+    source: Option<Div2Source>,
+) -> Option<Compared> {
+    let profile = if ubi::is_valid_profile_id(query) {
+        history::get_stored_profile_by_id(pool, &query.to_lowercase()).await
+    } else {
+        history::get_stored_profile_by_name(pool, platform, query, None).await
+    }
+    .ok()?;
+    // Without `source` a lookup asks tracker.gg first, so a statscard snapshot
+    // from a fallback doesn't stand in for one.
+    let source = match division {
+        Division::One => ubi::STATSCARD_SOURCE,
+        Division::Two => source.unwrap_or_default().as_str(),
+    };
+    let snapshot = get_latest_snapshot(pool, &profile.id, division.as_str(), Some(source))
+        .await
+        .ok()??;
+    let fetched_at = DateTime::parse_from_rfc3339(&snapshot.last_fetched_at).ok()?.with_timezone(&Utc);
+    if Utc::now() - fetched_at > Duration::seconds(config.compare_cache_secs as i64) {
+        return None;
+    }
+    Some(Compared {
+        profile,
+        source: snapshot.source,
+        cached: true,
+        fetched_at: snapshot.last_fetched_at,
+        stats: serde_json::from_str(&snapshot.stats).ok()?,
+    })
+}
+
+/// Stats of `query` from a recent snapshot, or fetched the way the single
+/// lookup routes do when there is none.
+async fn compared_stats(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    platform: Platform,
+    query: &str,
+    source: Option<Div2Source>,
+) -> UbiResult<Compared> {
+    if let Some(cached) = cached_stats(pool, config, division, platform, query, source).await {
+        return Ok(cached);
+    }
+    let by_id = ubi::is_valid_profile_id(query);
+    let id = query.to_lowercase();
+    let fetched = match division {
+        Division::One => {
+            let stats = if by_id {
+                ubi::get_div1_player_stats_by_id(pool, sessions, config, &id).await?
+            } else {
+                ubi::get_div1_player_stats(pool, sessions, config, platform, query, None).await?
+            };
+            stats.first().map(|s| (s.profile(), ubi::STATSCARD_SOURCE.to_string(), ubi::snapshot_stats(s)))
+        }
+        Division::Two => {
+            let stats = if by_id {
+                ubi::get_div2_player_stats_by_id(pool, sessions, config, &id, source).await?
+            } else {
+                ubi::get_div2_player_stats(pool, sessions, config, platform, query, None, source).await?
+            };
+            stats.first().map(|s| (s.profile(), s.source.as_str().to_string(), ubi::snapshot_stats(s)))
+        }
+    };
+    match fetched {
+        Some((profile, source, Value::Object(stats))) => Ok(Compared {
+            profile,
+            source,
+            cached: false,
+            fetched_at: Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            stats,
+        }),
+        _ => Err(UbiError::PlayerNotFound(query.to_string())),
+    }
+}
+
+/// Fields of the stats in their order, `extra` flattened to `extra.<statName>`.
+fn flatten_fields(stats: &Map<String, Value>) -> Vec<(String, Value)> {
+    let mut fields = vec![];
+    for (key, value) in stats {
+        match value {
+            Value::Object(extra) if key == "extra" => {
+                fields.extend(extra.iter().map(|(name, v)| (format!("extra.{}", name), v.clone())));
+            }
+            v => fields.push((key.clone(), v.clone())),
+        }
+    }
+    fields
+}
+
+/// Fields in the order the division's stats declare them; snapshots don't
+/// keep that order.
+fn field_order(division: Division) -> Vec<String> {
+    let template = match division {
+        Division::One => serde_json::json!(D1PlayerStats::default()),
+        // Fields only tracker.gg has are skipped while `None`.
+        Division::Two => serde_json::json!(D2PlayerStats {
+            shotgun_kills: Some(0),
+            smg_kills: Some(0),
+            pistol_kills: Some(0),
+            rifle_kills: Some(0),
+            pve_xp: Some(0),
+            pvp_xp: Some(0),
+            clan_xp: Some(0),
+            sharpshooter_kills: Some(0),
+            survivalist_kills: Some(0),
+            demolitionist_kills: Some(0),
+            commendation_count: Some(0),
+            rogue_playtime: Some(0),
+            longest_rogue: Some(0),
+            ..Default::default()
+        }),
+    };
+    template.as_object().map(|t| t.keys().cloned().collect()).unwrap_or_default()
+}
+
+/// `derived` of the stats, nested metrics flattened to `<group>.<name>`.
+fn derived_fields(division: Division, stats: &Map<String, Value>) -> Vec<(String, Value)> {
+    let stats = Value::Object(stats.clone());
+    let derived = match division {
+        Division::One => serde_json::from_value::<D1PlayerStats>(stats).map(|s| serde_json::json!(s.with_derived().derived)),
+        Division::Two => serde_json::from_value::<D2PlayerStats>(stats).map(|s| serde_json::json!(s.with_derived().derived)),
+    };
+    let mut fields = vec![];
+    for (key, value) in derived.ok().as_ref().and_then(|d| d.as_object()).into_iter().flatten() {
+        match value {
+            Value::Object(group) => fields.extend(group.iter().map(|(name, v)| (format!("{}.{}", key, name), v.clone()))),
+            v => fields.push((key.clone(), v.clone())),
+        }
+    }
+    fields
+}
+
+/// A table row: the players with the highest value lead, everyone else gets
+/// how far behind them they are, absolute and in percent of the leading value.
+/// Higher is better for every field: they are counters, scores or levels, and
+/// the `*_rank` fields are levels that go up, not positions on a ladder.
+fn compare_field(field: String, values: Vec<Value>, names: &[Option<String>]) -> CompareFieldDTO {
+    let numbers: Vec<Option<f64>> = values.iter().map(|v| v.as_f64()).collect();
+    let best = numbers.iter().flatten().cloned().fold(None, |best: Option<f64>, v| Some(best.map_or(v, |b| b.max(v))));
+    let leaders = match best {
+        Some(best) => numbers
+            .iter()
+            .zip(names)
+            .filter(|(v, _)| **v == Some(best))
+            .filter_map(|(_, name)| name.clone())
+            .collect(),
+        None => vec![],
+    };
+    let difference = numbers.iter().copied().map(|v| Some(number(v? - best?))).collect();
+    let difference_percent = numbers.iter().copied().map(|v| percent(v? - best?, best?)).collect();
+    CompareFieldDTO { field, values, leaders, difference, difference_percent }
+}
+
+/// One row per field any player has, `order` first and the rest as seen.
+fn table(fields: Vec<Vec<(String, Value)>>, order: &[String], names: &[Option<String>]) -> Vec<CompareFieldDTO> {
+    let mut field_names: Vec<&String> = vec![];
+    for (field, _) in fields.iter().flatten() {
+        if !field_names.contains(&field) {
+            field_names.push(field);
+        }
+    }
+    field_names.sort_by_key(|field| order.iter().position(|f| f == *field).unwrap_or(order.len()));
+    field_names
+        .into_iter()
+        .map(|field| {
+            let values = fields
+                .iter()
+                .map(|f| f.iter().find(|(k, _)| k == field).map(|(_, v)| v.clone()).unwrap_or(Value::Null))
+                .collect();
+            compare_field(field.clone(), values, names)
+        })
+        .collect()
+}
+
+/// Looks every player up, at most `upstream_concurrency` at a time, and lines
+/// their stats up field by field. A player that can't be looked up is reported
+/// in `players` and left blank in the table instead of failing the comparison.
+pub async fn compare_players(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    names: Vec<String>,
+    platform: Platform,
+    source: Option<Div2Source>,
+) -> CompareDTO {
+    let results = futures::stream::iter(names)
+        .map(|query| async move {
+            let r = compared_stats(pool, sessions, config, division, platform, &query, source).await;
+            (query, r)
+        })
+        .buffered(config.upstream_concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut players = vec![];
+    let mut compared = vec![];
+    for (query, r) in results {
+        match r {
+            Ok(c) => {
+                players.push(ComparePlayerDTO {
+                    query,
+                    status_code: ApiStatus::Ok.code(),
+                    id: Some(c.profile.id.clone()),
+                    name: c.profile.name.clone(),
+                    platform: Some(c.profile.platform),
+                    source: Some(c.source.clone()),
+                    cached: c.cached,
+                    fetched_at: Some(c.fetched_at.clone()),
+                    error: None,
+                });
+                compared.push(Some(c));
+            }
+            Err(err) => {
+                println!("Error: {}\nwhen comparing player {}", err, query);
+                let r = ResponseWithStatus::from(&err);
+                players.push(ComparePlayerDTO {
+                    query,
+                    status_code: r.status_code,
+                    id: None,
+                    name: None,
+                    platform: None,
+                    source: None,
+                    cached: false,
+                    fetched_at: None,
+                    error: Some(r.response),
+                });
+                compared.push(None);
+            }
+        }
+    }
+    let names: Vec<Option<String>> = compared
+        .iter()
+        .map(|c| c.as_ref().map(|c| c.profile.name.clone().unwrap_or_else(|| c.profile.id.clone())))
+        .collect();
+
+    let fields = table(
+        compared.iter().map(|c| c.as_ref().map(|c| flatten_fields(&c.stats)).unwrap_or_default()).collect(),
+        &field_order(division),
+        &names,
+    );
+    let derived = table(
+        compared.iter().map(|c| c.as_ref().map(|c| derived_fields(division, &c.stats)).unwrap_or_default()).collect(),
+        &[],
+        &names,
+    );
+
+    CompareDTO {
+        game: division.as_str().to_string(),
+        players,
+        fields,
+        derived,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_names_trims_and_skips_empty() {
+        assert_eq!(parse_names(Some(" a, b ,,c,")).unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn parse_names_bounds() {
+        assert!(parse_names(None).is_err());
+        assert!(parse_names(Some("a, ,")).is_err());
+        let names = |n: usize| (0..n).map(|i| format!("p{}", i)).collect::<Vec<_>>().join(",");
+        assert_eq!(parse_names(Some(&names(MAX_COMPARE_PLAYERS))).unwrap().len(), MAX_COMPARE_PLAYERS);
+        assert!(matches!(parse_names(Some(&names(MAX_COMPARE_PLAYERS + 1))), Err(UbiError::InvalidRequest(_))));
+    }
+
+    fn names(names: &[Option<&str>]) -> Vec<Option<String>> {
+        names.iter().map(|n| n.map(|n| n.to_string())).collect()
+    }
+
+    #[test]
+    fn compare_field_ties_lead_together() {
+        let row = compare_field("level".to_string(), vec![json!(40), json!(30), json!(40)], &names(&[Some("a"), Some("b"), Some("c")]));
+        assert_eq!(row.leaders, vec!["a", "c"]);
+        assert_eq!(row.difference, vec![Some(json!(0)), Some(json!(-10)), Some(json!(0))]);
+        assert_eq!(row.difference_percent, vec![Some(0.0), Some(-25.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn compare_field_skips_nulls_and_failed_players() {
+        let row = compare_field(
+            "smg_kills".to_string(),
+            vec![Value::Null, json!(200), json!("n/a"), Value::Null],
+            &names(&[Some("a"), Some("b"), Some("c"), None]),
+        );
+        assert_eq!(row.leaders, vec!["b"]);
+        assert_eq!(row.difference, vec![None, Some(json!(0)), None, None]);
+        assert_eq!(row.difference_percent, vec![None, Some(0.0), None, None]);
+    }
+
+    #[test]
+    fn compare_field_without_numbers_or_with_a_zero_leader() {
+        let row = compare_field("name".to_string(), vec![json!("a"), Value::Null], &names(&[Some("a"), Some("b")]));
+        assert!(row.leaders.is_empty());
+        assert_eq!(row.difference, vec![None, None]);
+
+        let row = compare_field("rogue_kills".to_string(), vec![json!(0), json!(0)], &names(&[Some("a"), Some("b")]));
+        assert_eq!(row.leaders, vec!["a", "b"]);
+        assert_eq!(row.difference_percent, vec![None, None]);
+    }
+}
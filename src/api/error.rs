@@ -0,0 +1,168 @@
+use serde_json::json;
+use thiserror::Error;
+
+use crate::model::response::{ApiStatus, ResponseWithStatus};
+use crate::model::ubi::CandidateDTO;
+
+pub type UbiResult<T> = Result<T, UbiError>;
+
+/// Why a lookup against Ubisoft / tracker.gg failed, so the API can answer with
+/// a proper status and a stable `error_code` instead of a blanket 404.
+#[derive(Debug, Error)]
+pub enum UbiError {
+    #[error("player {0} not found")]
+    PlayerNotFound(String),
+    #[error("{0} matches {} players, pick one with ?pick=<id> or the /id/<id> route", .1.len())]
+    MultipleMatches(String, Vec<CandidateDTO>),
+    #[error("player {0} exists but has no profile for this game")]
+    NoGameProfile(String),
+    #[error("upstream unavailable: {0}")]
+    UpstreamUnavailable(String),
+    #[error("ubi authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("rate limited by upstream: {0}")]
+    RateLimited(String),
+    #[error("failed to parse upstream response: {0}")]
+    ParseError(String),
+    #[error("unknown platform {0}, expected uplay, psn or xbl")]
+    InvalidPlatform(String),
+    #[error("{0} is not a Ubisoft profile id")]
+    InvalidProfileId(String),
+    #[error("no stored stats for player {0} yet")]
+    NoHistory(String),
+    #[error("unknown game {0}, see /api/games")]
+    UnknownGame(String),
+    #[error("player {0} is not tracked for this game")]
+    NotTracked(String),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl UbiError {
+    pub fn status(&self) -> ApiStatus {
+        match self {
+            UbiError::PlayerNotFound(_) => ApiStatus::NotFound,
+            UbiError::MultipleMatches(..) => ApiStatus::MultipleChoices,
+            UbiError::NoGameProfile(_) => ApiStatus::NotFound,
+            UbiError::UpstreamUnavailable(_) => ApiStatus::BadGateway,
+            UbiError::AuthFailed(_) => ApiStatus::ServiceUnavailable,
+            UbiError::RateLimited(_) => ApiStatus::TooManyRequests,
+            UbiError::ParseError(_) => ApiStatus::BadGateway,
+            UbiError::InvalidPlatform(_) => ApiStatus::BadRequest,
+            UbiError::InvalidProfileId(_) => ApiStatus::BadRequest,
+            UbiError::NoHistory(_) => ApiStatus::NotFound,
+            UbiError::UnknownGame(_) => ApiStatus::NotFound,
+            UbiError::NotTracked(_) => ApiStatus::NotFound,
+            UbiError::InvalidRequest(_) => ApiStatus::BadRequest,
+            UbiError::Internal(_) => ApiStatus::InternalServerError,
+        }
+    }
+
+    /// Machine readable code returned as `error_code`; never change existing values.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            UbiError::PlayerNotFound(_) => "PLAYER_NOT_FOUND",
+            UbiError::MultipleMatches(..) => "MULTIPLE_MATCHES",
+            UbiError::NoGameProfile(_) => "NO_GAME_PROFILE",
+            UbiError::UpstreamUnavailable(_) => "UPSTREAM_UNAVAILABLE",
+            UbiError::AuthFailed(_) => "AUTH_FAILED",
+            UbiError::RateLimited(_) => "RATE_LIMITED",
+            UbiError::ParseError(_) => "PARSE_ERROR",
+            UbiError::InvalidPlatform(_) => "INVALID_PLATFORM",
+            UbiError::InvalidProfileId(_) => "INVALID_PROFILE_ID",
+            UbiError::NoHistory(_) => "NO_HISTORY",
+            UbiError::UnknownGame(_) => "UNKNOWN_GAME",
+            UbiError::NotTracked(_) => "NOT_TRACKED",
+            UbiError::InvalidRequest(_) => "INVALID_REQUEST",
+            UbiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl From<&UbiError> for ResponseWithStatus {
+    fn from(err: &UbiError) -> Self {
+        let mut r = ResponseWithStatus::error(err.status(), err.error_code(), err.to_string());
+        if let UbiError::MultipleMatches(_, candidates) = err {
+            r.response.data = Some(json!({ "candidates": candidates }));
+        }
+        r
+    }
+}
+
+impl From<reqwest::Error> for UbiError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            UbiError::ParseError(err.to_string())
+        } else {
+            UbiError::UpstreamUnavailable(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for UbiError {
+    fn from(err: serde_json::Error) -> Self {
+        UbiError::ParseError(err.to_string())
+    }
+}
+
+impl From<thirtyfour::error::WebDriverError> for UbiError {
+    fn from(err: thirtyfour::error::WebDriverError) -> Self {
+        UbiError::UpstreamUnavailable(format!("browser: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ubi::Platform;
+
+    #[test]
+    fn errors_map_to_status_and_code() {
+        let s = || "x".to_string();
+        let cases = [
+            (UbiError::PlayerNotFound(s()), 404, "PLAYER_NOT_FOUND"),
+            (UbiError::MultipleMatches(s(), vec![]), 300, "MULTIPLE_MATCHES"),
+            (UbiError::NoGameProfile(s()), 404, "NO_GAME_PROFILE"),
+            (UbiError::UpstreamUnavailable(s()), 502, "UPSTREAM_UNAVAILABLE"),
+            (UbiError::AuthFailed(s()), 503, "AUTH_FAILED"),
+            (UbiError::RateLimited(s()), 429, "RATE_LIMITED"),
+            (UbiError::ParseError(s()), 502, "PARSE_ERROR"),
+            (UbiError::InvalidPlatform(s()), 400, "INVALID_PLATFORM"),
+            (UbiError::InvalidProfileId(s()), 400, "INVALID_PROFILE_ID"),
+            (UbiError::NoHistory(s()), 404, "NO_HISTORY"),
+            (UbiError::UnknownGame(s()), 404, "UNKNOWN_GAME"),
+            (UbiError::NotTracked(s()), 404, "NOT_TRACKED"),
+            (UbiError::InvalidRequest(s()), 400, "INVALID_REQUEST"),
+            (UbiError::Internal(s()), 500, "INTERNAL_ERROR"),
+        ];
+        for (err, status, code) in cases {
+            let r = ResponseWithStatus::from(&err);
+            assert_eq!(r.status_code, status, "{:?}", err);
+            assert_eq!(r.response.error_code.as_deref(), Some(code), "{:?}", err);
+            assert_eq!(r.response.message, err.to_string());
+        }
+    }
+
+    #[test]
+    fn multiple_matches_lists_candidates() {
+        let candidate = CandidateDTO {
+            id: "00000000-0000-4000-8000-000000000001".to_string(),
+            platform: Platform::Uplay,
+            current_name: "Agent".to_string(),
+            held_from: None,
+            held_until: None,
+        };
+        let r = ResponseWithStatus::from(&UbiError::MultipleMatches("Agent".to_string(), vec![candidate]));
+        let data = r.response.data.unwrap();
+        assert_eq!(data["candidates"][0]["id"], "00000000-0000-4000-8000-000000000001");
+        assert_eq!(data["candidates"][0]["current_name"], "Agent");
+    }
+
+    #[test]
+    fn undecodable_json_is_a_parse_error() {
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert_eq!(UbiError::from(err).error_code(), "PARSE_ERROR");
+    }
+}
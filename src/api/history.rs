@@ -0,0 +1,275 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+use serde_json::{Map, Value};
+use sqlx::{Pool, Sqlite};
+use std::collections::BTreeMap;
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::progress::{number, parse_since};
+use crate::api::ubi;
+use crate::db::snapshot::{get_latest_snapshot, get_snapshots_between};
+use crate::db::user::get_user_names_by_id;
+use crate::model::div::{Div2Source, Division};
+use crate::model::snapshot::{HistoryDTO, HistoryPointDTO};
+use crate::model::ubi::{Platform, ProfileDTO};
+
+pub const DEFAULT_FROM: &str = "365d";
+/// Most buckets one request may span, a year of days fits.
+const MAX_BUCKETS: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bucket {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl Bucket {
+    fn parse(s: &str) -> Option<Bucket> {
+        match s.to_lowercase().as_str() {
+            "hour" => Some(Bucket::Hour),
+            "day" => Some(Bucket::Day),
+            "week" => Some(Bucket::Week),
+            "month" => Some(Bucket::Month),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Bucket::Hour => "hour",
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+            Bucket::Month => "month",
+        }
+    }
+
+    /// Shortest length of a bucket, to bound how many a range spans.
+    fn min_len(&self) -> Duration {
+        match self {
+            Bucket::Hour => Duration::hours(1),
+            Bucket::Day => Duration::days(1),
+            Bucket::Week => Duration::weeks(1),
+            Bucket::Month => Duration::days(28),
+        }
+    }
+
+    /// Start of the bucket `t` falls in; weeks start on Monday.
+    fn start(&self, t: DateTime<Utc>) -> DateTime<Utc> {
+        let day = t.date_naive();
+        let start = match self {
+            Bucket::Hour => day.and_hms_opt(t.hour(), 0, 0),
+            Bucket::Day => day.and_hms_opt(0, 0, 0),
+            Bucket::Week => (day - Duration::days(day.weekday().num_days_from_monday() as i64)).and_hms_opt(0, 0, 0),
+            Bucket::Month => day.with_day(1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+        };
+        start.map(|s| Utc.from_utc_datetime(&s)).unwrap_or(t)
+    }
+}
+
+/// A snapshot field name: `gear_score`, `extra.<statName>`, ...
+pub fn is_valid_field(field: &str) -> bool {
+    !field.is_empty()
+        && field.len() <= 64
+        && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// A snapshot field, `extra.<statName>` for the unmapped statscards.
+fn field_value(stats: &Map<String, Value>, field: &str) -> Option<f64> {
+    match field.strip_prefix("extra.") {
+        Some(stat_name) => stats.get("extra")?.get(stat_name)?.as_f64(),
+        None => stats.get(field)?.as_f64(),
+    }
+}
+
+fn parse_time(t: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(t).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// `from..to` must be a non-empty range of at most `MAX_BUCKETS` buckets.
+fn check_range(from: DateTime<Utc>, to: DateTime<Utc>, bucket: Bucket) -> UbiResult<()> {
+    if from >= to {
+        return Err(UbiError::InvalidRequest("from must be before to".to_string()));
+    }
+    if (to - from).num_seconds() / bucket.min_len().num_seconds() > MAX_BUCKETS {
+        return Err(UbiError::InvalidRequest(format!(
+            "from..to spans more than {} {}s, use a coarser bucket",
+            MAX_BUCKETS,
+            bucket.as_str()
+        )));
+    }
+    Ok(())
+}
+
+/// Stored profile of `name`, without asking Ubisoft: history only has what we fetched.
+pub async fn get_stored_profile_by_name(pool: &Pool<Sqlite>, platform: Platform, name: &str, pick: Option<&str>) -> UbiResult<ProfileDTO> {
+    let profiles = ubi::find_player_id_by_db(pool, platform, name).await?;
+    if profiles.is_empty() {
+        return Err(UbiError::NoHistory(name.to_string()));
+    }
+    let mut profile = ubi::pick_profile(pool, platform, name, profiles, pick).await?.remove(0);
+    profile.name = get_user_names_by_id(pool, &profile.id)
+        .await
+        .ok()
+        .and_then(|names| names.into_iter().next());
+    Ok(profile)
+}
+
+pub async fn get_stored_profile_by_id(pool: &Pool<Sqlite>, id: &str) -> UbiResult<ProfileDTO> {
+    match ubi::get_stored_player_by_id(pool, id).await?.pop() {
+        Some(p) => Ok(ProfileDTO { id: p.id, platform: p.platform, name: p.all_names.into_iter().next() }),
+        None => Err(UbiError::NoHistory(id.to_string())),
+    }
+}
+
+/// Query parameters of the history routes, as sent.
+pub struct HistoryQuery<'a> {
+    pub field: Option<&'a str>,
+    pub from: Option<&'a str>,
+    pub to: Option<&'a str>,
+    pub bucket: Option<&'a str>,
+}
+
+/// `field` of the player's snapshots from `from` to `to`, one point per
+/// `bucket` with the last, lowest and highest value fetched in it.
+pub async fn get_history(
+    pool: &Pool<Sqlite>,
+    division: Division,
+    profile: ProfileDTO,
+    query: &HistoryQuery<'_>,
+    source: Option<Div2Source>,
+) -> UbiResult<HistoryDTO> {
+    let field = match query.field.map(|f| f.trim()) {
+        Some(f) if is_valid_field(f) => f,
+        _ => return Err(UbiError::InvalidRequest("field must be a stats field like gear_score or extra.<statName>".to_string())),
+    };
+    let bucket = match query.bucket {
+        Some(b) => Bucket::parse(b).ok_or_else(|| UbiError::InvalidRequest(format!("unknown bucket {}, expected hour, day, week or month", b)))?,
+        None => Bucket::Day,
+    };
+    let now = Utc::now();
+    let time = |name: &str, value: &str| {
+        parse_since(value, now).ok_or_else(|| UbiError::InvalidRequest(format!("{} must be a time like 30d, 2w or 2023-05-01, got {}", name, value)))
+    };
+    let from = time("from", query.from.unwrap_or(DEFAULT_FROM))?;
+    let to = match query.to {
+        Some(to) => time("to", to)?,
+        None => now,
+    };
+    check_range(from, to, bucket)?;
+
+    let internal = |e: anyhow::Error| UbiError::Internal(format!("Failed to get snapshots of {}: {}", profile.id, e));
+    let game = division.as_str();
+    let source = match source {
+        Some(s) => s.as_str().to_string(),
+        None => get_latest_snapshot(pool, &profile.id, game, None)
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| UbiError::NoHistory(profile.id.clone()))?
+            .source,
+    };
+    let snapshots = get_snapshots_between(
+        pool,
+        &profile.id,
+        game,
+        &source,
+        &from.format("%Y-%m-%d %H:%M:%S").to_string(),
+        &to.format("%Y-%m-%d %H:%M:%S").to_string(),
+    )
+    .await
+    .map_err(internal)?;
+
+    // a snapshot was seen when first fetched and every time since until `last_fetched_at`
+    let mut seen = vec![];
+    let mut has_field = snapshots.is_empty();
+    for snapshot in &snapshots {
+        let stats: Map<String, Value> = serde_json::from_str(&snapshot.stats)?;
+        let value = match field_value(&stats, field) {
+            Some(v) => v,
+            None => continue,
+        };
+        has_field = true;
+        for t in [&snapshot.fetched_at, &snapshot.last_fetched_at] {
+            if let Some(t) = parse_time(t).filter(|t| *t >= from && *t <= to) {
+                seen.push((t, value));
+            }
+        }
+    }
+    if !has_field {
+        return Err(UbiError::InvalidRequest(format!("{} stats have no numeric field {}", game, field)));
+    }
+    seen.sort_by_key(|(t, _)| *t);
+
+    let mut buckets: BTreeMap<DateTime<Utc>, (f64, f64, f64)> = BTreeMap::new();
+    for (t, v) in seen {
+        buckets
+            .entry(bucket.start(t))
+            .and_modify(|(last, min, max)| {
+                *last = v;
+                *min = min.min(v);
+                *max = max.max(v);
+            })
+            .or_insert((v, v, v));
+    }
+
+    Ok(HistoryDTO {
+        id: profile.id,
+        name: profile.name.unwrap_or_default(),
+        platform: profile.platform,
+        game: game.to_string(),
+        source,
+        field: field.to_string(),
+        bucket: bucket.as_str().to_string(),
+        from: from.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        to: to.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        points: buckets
+            .into_iter()
+            .map(|(t, (last, min, max))| HistoryPointDTO {
+                t: t.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                value: number(last),
+                min: number(min),
+                max: number(max),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn bucket_start() {
+        // a Wednesday
+        let t = at(2024, 5, 22, 13, 45);
+        assert_eq!(Bucket::Hour.start(t), at(2024, 5, 22, 13, 0));
+        assert_eq!(Bucket::Day.start(t), at(2024, 5, 22, 0, 0));
+        assert_eq!(Bucket::Week.start(t), at(2024, 5, 20, 0, 0));
+        assert_eq!(Bucket::Month.start(t), at(2024, 5, 1, 0, 0));
+        // weeks across a month and a year
+        assert_eq!(Bucket::Week.start(at(2024, 6, 2, 23, 59)), at(2024, 5, 27, 0, 0));
+        assert_eq!(Bucket::Week.start(at(2025, 1, 1, 0, 0)), at(2024, 12, 30, 0, 0));
+        assert_eq!(Bucket::Week.start(at(2024, 5, 20, 0, 0)), at(2024, 5, 20, 0, 0));
+    }
+
+    #[test]
+    fn range_is_bounded_by_max_buckets() {
+        let from = at(2024, 1, 1, 0, 0);
+        assert!(check_range(from, from + Duration::days(MAX_BUCKETS), Bucket::Day).is_ok());
+        assert!(check_range(from, from + Duration::days(MAX_BUCKETS + 1), Bucket::Day).is_err());
+        assert!(check_range(from, from + Duration::hours(MAX_BUCKETS + 1), Bucket::Hour).is_err());
+        assert!(check_range(from, from + Duration::hours(MAX_BUCKETS + 1), Bucket::Day).is_ok());
+        assert!(check_range(from, from + Duration::days(28 * MAX_BUCKETS), Bucket::Month).is_ok());
+    }
+
+    #[test]
+    fn range_must_not_be_empty() {
+        let from = at(2024, 1, 1, 0, 0);
+        assert!(matches!(check_range(from, from, Bucket::Day), Err(UbiError::InvalidRequest(_))));
+        assert!(check_range(from, from - Duration::hours(1), Bucket::Day).is_err());
+    }
+}
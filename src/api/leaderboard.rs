@@ -0,0 +1,88 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::history::is_valid_field;
+use crate::api::progress::parse_since;
+use crate::db::snapshot::{self, has_stat, LeaderboardFilter};
+use crate::model::div::{Div2Source, Division};
+use crate::model::snapshot::LeaderboardDTO;
+use crate::model::ubi::Platform;
+
+pub const LEADERBOARD_DEFAULT_LIMIT: i64 = 50;
+pub const LEADERBOARD_MAX_LIMIT: i64 = 100;
+
+/// Query parameters of the leaderboard routes, as sent.
+pub struct LeaderboardQuery<'a> {
+    pub stat: Option<&'a str>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub min_level: Option<f64>,
+    /// Only players whose stat was fetched within this long, like `30d`.
+    pub max_age: Option<&'a str>,
+    pub platform: Option<&'a str>,
+    /// `ubi` or `tracker` (Division 2 only).
+    pub source: Option<&'a str>,
+}
+
+/// Ranks every player we have fetched by the latest known value of a stat.
+/// There is no Ubisoft leaderboard behind this, only what was looked up here.
+pub async fn get_leaderboard(pool: &Pool<Sqlite>, division: Division, query: &LeaderboardQuery<'_>) -> UbiResult<LeaderboardDTO> {
+    let stat = match query.stat.map(|s| s.trim()) {
+        Some(s) if is_valid_field(s) => s,
+        _ => return Err(UbiError::InvalidRequest("stat must be a stats field like dz_rank or extra.<statName>".to_string())),
+    };
+    let limit = query.limit.unwrap_or(LEADERBOARD_DEFAULT_LIMIT);
+    if !(1..=LEADERBOARD_MAX_LIMIT).contains(&limit) {
+        return Err(UbiError::InvalidRequest(format!("limit must be 1 to {}", LEADERBOARD_MAX_LIMIT)));
+    }
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(UbiError::InvalidRequest("offset must not be negative".to_string()));
+    }
+    let platform = match query.platform {
+        Some(p) => Some(Platform::parse(p).ok_or_else(|| UbiError::InvalidPlatform(p.to_string()))?),
+        None => None,
+    };
+    let source = match query.source {
+        Some(s) => Some(
+            Div2Source::parse(s)
+                .ok_or_else(|| UbiError::InvalidRequest(format!("unknown source {}, expected ubi or tracker", s)))?
+                .as_str(),
+        ),
+        None => None,
+    };
+    let seen_since = match query.max_age {
+        Some(age) => Some(
+            parse_since(age, Utc::now())
+                .ok_or_else(|| UbiError::InvalidRequest(format!("max_age must be like 30d, 12h or 2w, got {}", age)))?
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        ),
+        None => None,
+    };
+
+    let game = division.as_str();
+    let internal = |e: anyhow::Error| UbiError::Internal(format!("Failed to rank {} {}: {}", game, stat, e));
+    if !has_stat(pool, game, stat, source).await.map_err(internal)? {
+        return Err(UbiError::InvalidRequest(format!("no {} player has a numeric {} recorded", game, stat)));
+    }
+    let filter = LeaderboardFilter {
+        min_level: query.min_level,
+        seen_since: seen_since.as_deref(),
+        platform: platform.map(|p| p.as_str()),
+        source,
+    };
+    let (entries, total) = snapshot::get_leaderboard(pool, game, stat, &filter, limit, offset)
+        .await
+        .map_err(internal)?;
+
+    Ok(LeaderboardDTO {
+        game: game.to_string(),
+        stat: stat.to_string(),
+        total,
+        limit,
+        offset,
+        entries,
+    })
+}
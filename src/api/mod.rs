@@ -1,2 +1,10 @@
+pub mod compare;
+pub mod error;
+pub mod history;
+pub mod leaderboard;
+pub mod player;
+pub mod progress;
+pub mod session;
+pub mod tracked;
 pub mod ubi;
 pub mod wrapper;
\ No newline at end of file
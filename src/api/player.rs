@@ -0,0 +1,53 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::ubi::is_valid_profile_id;
+use crate::db::user::{get_user_name_history_by_id, get_user_platform_by_id, search_user_names};
+use crate::model::ubi::{NameTimelineDTO, Platform, PlayerMatchDTO};
+
+pub const SEARCH_DEFAULT_LIMIT: i64 = 10;
+pub const SEARCH_MAX_LIMIT: i64 = 50;
+const SEARCH_MAX_QUERY_LEN: usize = 32;
+
+/// Suggests known players for a name prefix without asking Ubisoft.
+pub async fn search_players(
+    pool: &Pool<Sqlite>,
+    q: &str,
+    platform: Option<Platform>,
+    limit: Option<i64>,
+) -> UbiResult<Vec<PlayerMatchDTO>> {
+    let q = q.trim();
+    if q.is_empty() || q.chars().count() > SEARCH_MAX_QUERY_LEN {
+        return Err(UbiError::InvalidRequest(format!("q must be 1 to {} characters", SEARCH_MAX_QUERY_LEN)));
+    }
+    let limit = limit.unwrap_or(SEARCH_DEFAULT_LIMIT);
+    if !(1..=SEARCH_MAX_LIMIT).contains(&limit) {
+        return Err(UbiError::InvalidRequest(format!("limit must be 1 to {}", SEARCH_MAX_LIMIT)));
+    }
+
+    search_user_names(pool, q, platform.map(|p| p.as_str()), limit)
+        .await
+        .map_err(|e| UbiError::Internal(format!("Failed to search players {}: {}", q, e)))
+}
+
+/// All names we have seen `id` use, with when we first and last saw each.
+pub async fn get_name_timeline(pool: &Pool<Sqlite>, id: &str) -> UbiResult<NameTimelineDTO> {
+    if !is_valid_profile_id(id) {
+        return Err(UbiError::InvalidProfileId(id.to_string()));
+    }
+    let id = id.to_lowercase();
+    let internal = |e: anyhow::Error| UbiError::Internal(format!("Failed to get names of {}: {}", id, e));
+
+    let platform = get_user_platform_by_id(pool, &id)
+        .await
+        .map_err(internal)?
+        .and_then(|p| Platform::parse(&p))
+        .ok_or_else(|| UbiError::PlayerNotFound(id.clone()))?;
+    let names = get_user_name_history_by_id(pool, &id).await.map_err(internal)?;
+    let current_name = names
+        .first()
+        .map(|n| n.name.clone())
+        .ok_or_else(|| UbiError::PlayerNotFound(id.clone()))?;
+
+    Ok(NameTimelineDTO { id, platform, current_name, names })
+}
@@ -0,0 +1,298 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use serde_json::{Map, Value};
+use sqlx::{Pool, Sqlite};
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::session::UbiSessionPool;
+use crate::api::ubi;
+use crate::config::AppConfig;
+use crate::db::snapshot::{get_latest_snapshot, get_snapshot_at};
+use crate::model::div::{Div2Source, Division};
+use crate::model::snapshot::ProgressDTO;
+use crate::model::ubi::{Platform, ProfileDTO, StoredPlayerDTO};
+
+pub const DEFAULT_SINCE: &str = "7d";
+const MAX_SINCE_AMOUNT: i64 = 100_000;
+/// Counters worth a per-hour rate; levels, ranks and gear score aren't.
+const RATE_FIELD_MARKERS: &[&str] = &["kill", "xp", "headshot", "extracted"];
+
+/// `30m`, `12h`, `7d`, `2w` back from `now`, or an RFC 3339 time or `YYYY-MM-DD` date.
+pub fn parse_since(s: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    let unit = s.chars().last()?;
+    if let Ok(n) = s[..s.len() - unit.len_utf8()].parse::<i64>() {
+        if !(0..=MAX_SINCE_AMOUNT).contains(&n) {
+            return None;
+        }
+        let ago = match unit {
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            'w' => Duration::weeks(n),
+            _ => return None,
+        };
+        return Some(now - ago);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| Utc.from_utc_datetime(&t))
+        })
+}
+
+fn since_or_error(since: Option<&str>) -> UbiResult<DateTime<Utc>> {
+    let since = since.unwrap_or(DEFAULT_SINCE);
+    let now = Utc::now();
+    match parse_since(since, now) {
+        Some(t) if t <= now => Ok(t),
+        _ => Err(UbiError::InvalidRequest(format!(
+            "since must be a past time like 7d, 12h, 2w or 2023-05-01, got {}",
+            since
+        ))),
+    }
+}
+
+/// The single stored profile to fall back to when a fresh lookup failed with `err`.
+async fn stored_profile(
+    err: UbiError,
+    pick: Option<&str>,
+    stored: impl Future<Output = UbiResult<Vec<StoredPlayerDTO>>>,
+) -> UbiResult<(ProfileDTO, bool)> {
+    if !matches!(err, UbiError::UpstreamUnavailable(_) | UbiError::AuthFailed(_) | UbiError::RateLimited(_)) {
+        return Err(err);
+    }
+    let mut players = stored.await?;
+    if let Some(pick) = pick {
+        players.retain(|p| p.id.eq_ignore_ascii_case(pick));
+    }
+    match players.pop() {
+        Some(p) if players.is_empty() => Ok((
+            ProfileDTO { id: p.id, platform: p.platform, name: p.all_names.into_iter().next() },
+            false,
+        )),
+        _ => Err(err),
+    }
+}
+
+/// Fetches the player's stats first so the latest snapshot is current, then
+/// compares it with the snapshot in effect at `since`.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_progress(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    platform: Platform,
+    name: &str,
+    pick: Option<&str>,
+    since: Option<&str>,
+    source: Option<Div2Source>,
+) -> UbiResult<ProgressDTO> {
+    let since = since_or_error(since)?;
+    let fetched = match division {
+        Division::One => ubi::get_div1_player_stats(pool, sessions, config, platform, name, pick)
+            .await
+            .map(|s| s.first().map(|s| s.profile())),
+        Division::Two => ubi::get_div2_player_stats(pool, sessions, config, platform, name, pick, source)
+            .await
+            .map(|s| s.first().map(|s| s.profile())),
+    };
+    let (profile, refreshed) = match fetched {
+        Ok(Some(p)) => (p, true),
+        Ok(None) => return Err(UbiError::PlayerNotFound(name.to_string())),
+        Err(e) => stored_profile(e, pick, ubi::get_stored_players_by_name(pool, platform, name)).await?,
+    };
+    compare_snapshots(pool, division, profile, refreshed, since, source).await
+}
+
+pub async fn get_progress_by_id(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    id: &str,
+    since: Option<&str>,
+    source: Option<Div2Source>,
+) -> UbiResult<ProgressDTO> {
+    let since = since_or_error(since)?;
+    let fetched = match division {
+        Division::One => ubi::get_div1_player_stats_by_id(pool, sessions, config, id)
+            .await
+            .map(|s| s.first().map(|s| s.profile())),
+        Division::Two => ubi::get_div2_player_stats_by_id(pool, sessions, config, id, source)
+            .await
+            .map(|s| s.first().map(|s| s.profile())),
+    };
+    let (profile, refreshed) = match fetched {
+        Ok(Some(p)) => (p, true),
+        Ok(None) => return Err(UbiError::PlayerNotFound(id.to_string())),
+        Err(e) => stored_profile(e, None, ubi::get_stored_player_by_id(pool, id)).await?,
+    };
+    compare_snapshots(pool, division, profile, refreshed, since, source).await
+}
+
+/// An integer when `v` has no fraction, so counters don't come out as `42.0`.
+pub fn number(v: f64) -> Value {
+    if v.fract() == 0.0 && v.abs() < i64::MAX as f64 {
+        Value::from(v as i64)
+    } else {
+        Value::from(v)
+    }
+}
+
+/// `latest - baseline` of every numeric field both have, `extra` flattened to `extra.<statName>`.
+fn collect_deltas(prefix: &str, baseline: &Map<String, Value>, latest: &Map<String, Value>, deltas: &mut BTreeMap<String, Value>) {
+    for (key, value) in latest {
+        match (value, baseline.get(key)) {
+            (Value::Object(latest), Some(Value::Object(baseline))) if key == "extra" => {
+                collect_deltas("extra.", baseline, latest, deltas);
+            }
+            (Value::Number(l), Some(Value::Number(b))) => {
+                if let (Some(l), Some(b)) = (l.as_f64(), b.as_f64()) {
+                    deltas.insert(format!("{}{}", prefix, key), number(l - b));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Hours played between the snapshots, from the seconds when both have them and
+/// from the whole hours of older snapshots otherwise.
+fn playtime_hours(division: Division, deltas: &BTreeMap<String, Value>) -> f64 {
+    match deltas.get(division.playtime_secs_field()).and_then(|v| v.as_f64()) {
+        Some(secs) => secs / 3600.0,
+        None => deltas.get(division.playtime_field()).and_then(|v| v.as_f64()).unwrap_or(0.0),
+    }
+}
+
+async fn compare_snapshots(
+    pool: &Pool<Sqlite>,
+    division: Division,
+    profile: ProfileDTO,
+    refreshed: bool,
+    since: DateTime<Utc>,
+    source: Option<Div2Source>,
+) -> UbiResult<ProgressDTO> {
+    let internal = |e: anyhow::Error| UbiError::Internal(format!("Failed to get snapshots of {}: {}", profile.id, e));
+    let game = division.as_str();
+    let latest = get_latest_snapshot(pool, &profile.id, game, source.map(|s| s.as_str()))
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| UbiError::NoHistory(profile.id.clone()))?;
+    let at = since.format("%Y-%m-%d %H:%M:%S").to_string();
+    let (baseline, complete) = get_snapshot_at(pool, &profile.id, game, &latest.source, &at)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| UbiError::NoHistory(profile.id.clone()))?;
+
+    let latest_stats: Map<String, Value> = serde_json::from_str(&latest.stats)?;
+    let baseline_stats: Map<String, Value> = serde_json::from_str(&baseline.stats)?;
+    let mut deltas = BTreeMap::new();
+    collect_deltas("", &baseline_stats, &latest_stats, &mut deltas);
+
+    let playtime_hours = playtime_hours(division, &deltas);
+    let per_playtime_hour = if playtime_hours > 0.0 {
+        deltas
+            .iter()
+            .filter(|(field, _)| RATE_FIELD_MARKERS.iter().any(|m| field.contains(m)) && !field.starts_with("extra."))
+            .filter_map(|(field, v)| v.as_f64().filter(|v| *v > 0.0).map(|v| (field.clone(), v)))
+            .map(|(field, v)| (field, (v / playtime_hours * 100.0).round() / 100.0))
+            .collect()
+    } else {
+        BTreeMap::new()
+    };
+    let playtime_hours = (playtime_hours * 100.0).round() / 100.0;
+
+    Ok(ProgressDTO {
+        id: profile.id,
+        name: profile.name.unwrap_or_default(),
+        platform: profile.platform,
+        game: game.to_string(),
+        source: latest.source,
+        since: since.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        from: baseline.fetched_at,
+        to: latest.last_fetched_at,
+        complete,
+        refreshed,
+        playtime_hours,
+        deltas,
+        per_playtime_hour,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 20, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_since_relative() {
+        assert_eq!(parse_since("30m", now()), Some(now() - Duration::minutes(30)));
+        assert_eq!(parse_since(" 12h ", now()), Some(now() - Duration::hours(12)));
+        assert_eq!(parse_since("7d", now()), Some(now() - Duration::days(7)));
+        assert_eq!(parse_since("2w", now()), Some(now() - Duration::weeks(2)));
+        assert_eq!(parse_since("0d", now()), Some(now()));
+    }
+
+    #[test]
+    fn parse_since_absolute() {
+        assert_eq!(parse_since("2024-05-01", now()), Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(
+            parse_since("2024-05-01T08:30:00+02:00", now()),
+            Some(Utc.with_ymd_and_hms(2024, 5, 1, 6, 30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_since_rejects_garbage() {
+        for s in ["", "d", "7", "7y", "-1d", "100001d", "9999999999999999999w", "7 d", "2024-13-01", "yesterday", "é"] {
+            assert_eq!(parse_since(s, now()), None, "{:?} parsed", s);
+        }
+        assert!(parse_since("100000w", now()).is_some());
+    }
+
+    fn deltas(baseline: Value, latest: Value) -> BTreeMap<String, Value> {
+        let mut deltas = BTreeMap::new();
+        collect_deltas("", baseline.as_object().unwrap(), latest.as_object().unwrap(), &mut deltas);
+        deltas
+    }
+
+    #[test]
+    fn collect_deltas_of_numbers_both_have() {
+        let d = deltas(
+            json!({"level": 30, "total_kills": 1000, "main_story_percent": 60.5, "gear_score": 250, "name": "a",
+                   "extra": {"missionsCompleted": 40, "gone": 1}}),
+            json!({"level": 30, "total_kills": 1200, "main_story_percent": 67.0, "npc_kills": 5, "name": "b",
+                   "extra": {"missionsCompleted": 42, "title": 3}}),
+        );
+        assert_eq!(
+            d.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("extra.missionsCompleted".to_string(), json!(2)),
+                ("level".to_string(), json!(0)),
+                ("main_story_percent".to_string(), json!(6.5)),
+                ("total_kills".to_string(), json!(200)),
+            ]
+        );
+    }
+
+    #[test]
+    fn playtime_hours_prefer_seconds() {
+        let d = deltas(json!({"playtime": 10, "playtime_secs": 36000}), json!({"playtime": 11, "playtime_secs": 41400}));
+        assert_eq!(playtime_hours(Division::One, &d), 1.5);
+        let d = deltas(json!({"total_playtime": 10}), json!({"total_playtime": 12}));
+        assert_eq!(playtime_hours(Division::Two, &d), 2.0);
+        assert_eq!(playtime_hours(Division::Two, &BTreeMap::new()), 0.0);
+    }
+}
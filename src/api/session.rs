@@ -0,0 +1,434 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::{HeaderMap, HeaderValue}, StatusCode};
+use serde_json::{json, Value};
+use sqlx::{Pool, Sqlite};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use anyhow::anyhow;
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::ubi::login_ubi;
+use crate::config::{AppConfig, UbiAccount};
+use crate::db::session::{get_session, store_session};
+use crate::model::ubi::{Platform, RefreshStatus, SessionDTO};
+use crate::util;
+
+/// Requests stop using a ticket this close to its expiration.
+pub const USABLE_MARGIN_SECONDS: i64 = 30;
+
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const BACKOFF_BASE: Duration = Duration::from_secs(5);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// The Ubisoft session of one service account. Ticket, session id and
+/// expiration are swapped together, and logins only happen at startup and in
+/// the background refresher, never inside a user request.
+pub struct UbiSession {
+    pool: Pool<Sqlite>,
+    account: UbiAccount,
+    services_url: String,
+    login_attempts: u32,
+    current: RwLock<Option<SessionDTO>>,
+    refresh: Mutex<()>,
+    status: RwLock<RefreshStatus>,
+    cooldown_until: RwLock<Option<DateTime<Utc>>>,
+}
+
+fn expires_at(session: &SessionDTO) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&session.expiration)
+        .ok()
+        .map(|exp| exp.with_timezone(&Utc))
+}
+
+fn is_usable(session: &Option<SessionDTO>) -> bool {
+    let margin = Utc::now() + chrono::Duration::seconds(USABLE_MARGIN_SECONDS);
+    session
+        .as_ref()
+        .and_then(expires_at)
+        .map(|exp| exp > margin)
+        .unwrap_or(false)
+}
+
+fn to_chrono(d: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(d).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+/// Exponential backoff capped at `BACKOFF_MAX`, plus up to 50% random jitter so
+/// several instances don't retry in lockstep.
+fn backoff(failures: u32) -> Duration {
+    let exp = BACKOFF_BASE.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)));
+    let delay = exp.min(BACKOFF_MAX);
+    let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+    delay + Duration::from_millis(jitter)
+}
+
+impl UbiSession {
+    pub fn new(pool: Pool<Sqlite>, account: UbiAccount, services_url: String, login_attempts: u32) -> Self {
+        UbiSession {
+            pool,
+            account,
+            services_url,
+            login_attempts,
+            current: RwLock::new(None),
+            refresh: Mutex::new(()),
+            status: RwLock::new(RefreshStatus::default()),
+            cooldown_until: RwLock::new(None),
+        }
+    }
+
+    /// Loads the last persisted session of the account, so a restart only logs in
+    /// again once the stored ticket has expired.
+    pub async fn restore(&self) -> anyhow::Result<bool> {
+        let session = match get_session(&self.pool, &self.account.username).await? {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        if expires_at(&session).is_none() {
+            println!("Ignored stored Ubi session with malformed expiration {}", session.expiration);
+            return Ok(false);
+        }
+        *self.current.write().await = Some(session);
+        Ok(true)
+    }
+
+    pub async fn expiration(&self) -> Option<DateTime<Utc>> {
+        self.current.read().await.as_ref().and_then(expires_at)
+    }
+
+    pub async fn status(&self) -> RefreshStatus {
+        self.status.read().await.clone()
+    }
+
+    /// Usable ticket and not cooling down after Ubisoft pushed back.
+    pub async fn is_healthy(&self) -> bool {
+        let cooling = self
+            .cooldown_until
+            .read()
+            .await
+            .map(|until| until > Utc::now())
+            .unwrap_or(false);
+        !cooling && is_usable(&*self.current.read().await)
+    }
+
+    pub async fn cool_down(&self, duration: Duration) {
+        let until = Utc::now() + to_chrono(duration);
+        println!("Put Ubi account {} on cooldown until {}", self.account.username, until.to_rfc3339());
+        *self.cooldown_until.write().await = Some(until);
+    }
+
+    /// Logs in once and swaps the new session in. Concurrent callers share the
+    /// same login instead of each starting their own.
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let before = self.expiration().await;
+        let _refreshing = self.refresh.lock().await;
+        // Someone else refreshed while we were waiting for the lock
+        if self.expiration().await != before && is_usable(&*self.current.read().await) {
+            return Ok(());
+        }
+
+        let remember_me_ticket = self
+            .current
+            .read()
+            .await
+            .as_ref()
+            .and_then(|s| s.remember_me_ticket.clone());
+        let result = login_ubi(&self.services_url, &self.account, remember_me_ticket.as_deref()).await;
+        let mut status = self.status.write().await;
+        match result {
+            Ok(session) => {
+                if let Err(e) = store_session(&self.pool, &self.account.username, &session).await {
+                    println!("Failed to persist Ubi session for {}: {:?}", self.account.username, e);
+                }
+                println!("Renewed Ubi ticket of {} at {}", self.account.username, Utc::now().to_rfc3339());
+                *self.current.write().await = Some(session);
+                status.last_success = Some(Utc::now());
+                status.consecutive_failures = 0;
+                Ok(())
+            }
+            Err(e) => {
+                status.last_failure = Some(Utc::now());
+                status.last_error = Some(e.to_string());
+                status.consecutive_failures += 1;
+                Err(e)
+            }
+        }
+    }
+
+    /// Makes sure there is a usable ticket, logging in up to `login_attempts`
+    /// times with backoff in between. Only meant for startup; afterwards the
+    /// refresher keeps it valid.
+    pub async fn ensure_valid(&self) -> anyhow::Result<()> {
+        let mut login_counts = 0;
+        while !is_usable(&*self.current.read().await) {
+            if login_counts >= self.login_attempts {
+                return Err(anyhow!("Failed to login after {} trials", self.login_attempts));
+            }
+            if login_counts > 0 {
+                tokio::time::sleep(backoff(login_counts)).await;
+            }
+            login_counts += 1;
+            if let Err(e) = self.refresh().await {
+                println!("Failed to login to ubi as {} ({}/{}): {}", self.account.username, login_counts, self.login_attempts, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Common headers plus `Authorization` and `Ubi-SessionId` of the current session.
+    pub async fn headers(&self, platform: Platform) -> anyhow::Result<HeaderMap> {
+        let session = self.current.read().await.clone();
+        if !is_usable(&session) {
+            return Err(anyhow!("Ubi session expired, waiting for the background refresh"));
+        }
+        let session = session.ok_or_else(|| anyhow!("No Ubi session available"))?;
+
+        let mut headers = util::header::get_common_header(platform).await;
+        headers.insert(
+            "Authorization",
+            format!("Ubi_v1 t={}", session.ticket).parse::<HeaderValue>()?,
+        );
+        headers.insert("Ubi-SessionId", session.session_id.parse::<HeaderValue>()?);
+        Ok(headers)
+    }
+}
+
+/// `margin` capped at half the lifetime of a ticket obtained at `logged_in`, so
+/// a margin longer than Ubisoft's tickets live doesn't turn into a login every
+/// `MIN_REFRESH_INTERVAL`. A restored ticket's login time isn't known.
+fn refresh_margin(margin: Duration, logged_in: Option<DateTime<Utc>>, exp: DateTime<Utc>) -> chrono::Duration {
+    let margin = to_chrono(margin);
+    match logged_in {
+        Some(at) if exp > at => margin.min((exp - at) / 2),
+        _ => margin,
+    }
+}
+
+/// Renews the session `margin` before it expires, backing off with jitter while
+/// Ubisoft keeps refusing the login.
+pub fn spawn_refresher(session: Arc<UbiSession>, margin: Duration) {
+    tokio::spawn(async move {
+        loop {
+            let wait = match session.expiration().await {
+                Some(exp) if is_usable(&*session.current.read().await) => {
+                    let margin = refresh_margin(margin, session.status.read().await.last_success, exp);
+                    (exp - margin - Utc::now()).to_std().unwrap_or_default().max(MIN_REFRESH_INTERVAL)
+                }
+                // Never logged in or already expired, e.g. after a degraded start
+                _ => Duration::ZERO,
+            };
+            session.status.write().await.next_refresh =
+                Some(Utc::now() + to_chrono(wait));
+            tokio::time::sleep(wait).await;
+
+            while let Err(e) = session.refresh().await {
+                let failures = session.status.read().await.consecutive_failures;
+                let delay = backoff(failures);
+                println!(
+                    "Background Ubi refresh of {} failed ({} in a row), retrying in {:?}: {}",
+                    session.account.username, failures, delay, e
+                );
+                session.status.write().await.next_refresh =
+                    Some(Utc::now() + to_chrono(delay));
+                tokio::time::sleep(delay).await;
+            }
+        }
+    });
+}
+
+/// All configured service accounts, held in Rocket `State`. Upstream calls are
+/// spread round-robin over the healthy accounts, and an account Ubisoft pushes
+/// back on is put on cooldown while its traffic fails over to the others.
+pub struct UbiSessionPool {
+    sessions: Vec<Arc<UbiSession>>,
+    next: AtomicUsize,
+    services_url: String,
+    cooldown: Duration,
+    client: reqwest::Client,
+}
+
+/// Shares the accounts and their sessions, for background work that can't
+/// borrow the pool from Rocket's `State`.
+impl Clone for UbiSessionPool {
+    fn clone(&self) -> Self {
+        UbiSessionPool {
+            sessions: self.sessions.clone(),
+            next: AtomicUsize::new(self.next.load(Ordering::Relaxed)),
+            services_url: self.services_url.clone(),
+            cooldown: self.cooldown,
+            client: self.client.clone(),
+        }
+    }
+}
+
+impl UbiSessionPool {
+    pub fn new(pool: Pool<Sqlite>, config: &AppConfig) -> Self {
+        UbiSessionPool {
+            sessions: config
+                .ubi_accounts
+                .iter()
+                .map(|account| {
+                    Arc::new(UbiSession::new(
+                        pool.clone(),
+                        account.clone(),
+                        config.ubi_services_url.clone(),
+                        config.ubi_login_attempts,
+                    ))
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            services_url: config.ubi_services_url.clone(),
+            cooldown: Duration::from_secs(config.ubi_account_cooldown_secs),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Base of every Ubisoft services endpoint, e.g. a local mock server.
+    pub fn services_url(&self) -> &str {
+        &self.services_url
+    }
+
+    /// Restores and validates every account; fails only if none of them can log in.
+    /// The server still starts in that case, and the refreshers keep retrying.
+    pub async fn ensure_valid(&self) -> anyhow::Result<()> {
+        let mut valid = 0;
+        for session in &self.sessions {
+            if session.restore().await? {
+                println!("Restored stored Ubi session of {}", session.account.username);
+            }
+            match session.ensure_valid().await {
+                Ok(_) => valid += 1,
+                Err(e) => println!("Ubi account {} is unavailable: {}", session.account.username, e),
+            }
+        }
+        if valid == 0 {
+            return Err(anyhow!("None of the {} Ubi accounts could login", self.sessions.len()));
+        }
+        Ok(())
+    }
+
+    pub fn spawn_refreshers(&self, margin: Duration) {
+        for session in &self.sessions {
+            spawn_refresher(session.clone(), margin);
+        }
+    }
+
+    /// Whether any account can currently reach Ubisoft.
+    pub async fn is_available(&self) -> bool {
+        for session in &self.sessions {
+            if session.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn status(&self) -> Value {
+        let mut accounts = vec![];
+        for (i, session) in self.sessions.iter().enumerate() {
+            accounts.push(json!({
+                "account": i,
+                "healthy": session.is_healthy().await,
+                "expiration": session.expiration().await,
+                "cooldown_until": *session.cooldown_until.read().await,
+                "refresher": session.status().await,
+            }));
+        }
+        json!(accounts)
+    }
+
+    /// Next healthy account in round-robin order.
+    async fn pick(&self) -> UbiResult<Arc<UbiSession>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.sessions.len() {
+            let session = &self.sessions[(start + i) % self.sessions.len()];
+            if session.is_healthy().await {
+                return Ok(session.clone());
+            }
+        }
+        for session in &self.sessions {
+            if is_usable(&*session.current.read().await) {
+                return Err(UbiError::RateLimited("every Ubi account is cooling down".to_string()));
+            }
+        }
+        Err(UbiError::AuthFailed("no Ubi account has a valid session".to_string()))
+    }
+
+    /// GETs a Ubisoft services url with an authenticated account. HTTP 429 and
+    /// `errorCode` responses put the account on cooldown and the call is retried
+    /// with the next one, except for 4xx errors about the request itself
+    /// (e.g. an unknown profile), which no other account would answer differently.
+    pub async fn get_json(&self, url: &str, platform: Platform) -> UbiResult<Value> {
+        let mut last_error = None;
+        for _ in 0..self.sessions.len() {
+            let session = match self.pick().await {
+                Ok(s) => s,
+                Err(e) => return Err(last_error.unwrap_or(e)),
+            };
+            let headers = session
+                .headers(platform)
+                .await
+                .map_err(|e| UbiError::AuthFailed(e.to_string()))?;
+            let resp = self.client.get(url).headers(headers).send().await?;
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+                session.cool_down(self.cooldown).await;
+                last_error = Some(UbiError::RateLimited(format!("account {}", session.account.username)));
+                continue;
+            }
+
+            let body = resp.json::<Value>().await?;
+            if body["errorCode"].is_null() {
+                return Ok(body);
+            }
+            println!("{:#?}", body);
+            let http_code = body["httpCode"].as_u64().unwrap_or(0);
+            if http_code == 404 {
+                return Err(UbiError::PlayerNotFound(body["message"].as_str().unwrap_or(url).to_string()));
+            }
+            if (400..500).contains(&http_code) && ![401, 403, 429].contains(&http_code) {
+                return Err(UbiError::UpstreamUnavailable(format!("Ubi rejected request {}: {}", url, body["message"])));
+            }
+            session.cool_down(self.cooldown).await;
+            last_error = Some(match http_code {
+                401 | 403 => UbiError::AuthFailed(format!("account {}: {}", session.account.username, body["message"])),
+                429 => UbiError::RateLimited(format!("account {}", session.account.username)),
+                _ => UbiError::UpstreamUnavailable(format!("Ubi returned error {}", body["errorCode"])),
+            });
+        }
+        Err(last_error.unwrap_or_else(|| UbiError::AuthFailed("no Ubi account configured".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_with_jitter() {
+        for (failures, base) in [(0, 5), (1, 5), (2, 10), (3, 20), (4, 40)] {
+            let delay = backoff(failures);
+            let base = Duration::from_secs(base);
+            assert!(delay >= base && delay <= base + base / 2, "{} failures waited {:?}", failures, delay);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        for failures in [7, 8, 32, u32::MAX] {
+            let delay = backoff(failures);
+            assert!(delay >= BACKOFF_MAX && delay <= BACKOFF_MAX + BACKOFF_MAX / 2, "{} failures waited {:?}", failures, delay);
+        }
+    }
+
+    #[test]
+    fn refresh_margin_is_capped_at_half_the_lifetime() {
+        let at = Utc::now();
+        let exp = at + chrono::Duration::hours(3);
+        let margin = |secs| refresh_margin(Duration::from_secs(secs), Some(at), exp);
+        assert_eq!(margin(600), chrono::Duration::minutes(10));
+        assert_eq!(margin(6 * 3600), chrono::Duration::minutes(90));
+        assert_eq!(refresh_margin(Duration::from_secs(6 * 3600), None, exp), chrono::Duration::hours(6));
+    }
+}
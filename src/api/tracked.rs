@@ -0,0 +1,143 @@
+use futures::StreamExt;
+use sqlx::{Pool, Sqlite};
+use std::time::Duration;
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::session::UbiSessionPool;
+use crate::api::ubi;
+use crate::config::AppConfig;
+use crate::db::tracked::{add_tracked_player, get_tracked_players, record_refresh, remove_tracked_player};
+use crate::db::user::{create_user, store_user_name};
+use crate::model::div::Division;
+use crate::model::response::{ApiStatus, ResponseWithStatus};
+use crate::model::tracked::{TrackRequest, TrackedPlayerDTO};
+use crate::model::ubi::Platform;
+
+/// Shortest wait between two refresh passes, whatever the config says.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+fn parse_game(game: &str) -> UbiResult<Division> {
+    Division::parse(game).ok_or_else(|| UbiError::InvalidRequest(format!("game must be div1 or div2, got {}", game)))
+}
+
+fn internal(e: anyhow::Error) -> UbiError {
+    UbiError::Internal(format!("Failed to access tracked players: {}", e))
+}
+
+pub async fn list_tracked_players(pool: &Pool<Sqlite>) -> UbiResult<Vec<TrackedPlayerDTO>> {
+    get_tracked_players(pool).await.map_err(internal)
+}
+
+/// Resolves the requested player with Ubisoft and starts tracking them. The
+/// tracked entry is returned with whether it was newly added.
+pub async fn track_player(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, request: &TrackRequest) -> UbiResult<(TrackedPlayerDTO, bool)> {
+    let division = parse_game(&request.game)?;
+    let player = request.player.trim();
+    let profile = if ubi::is_valid_profile_id(player) {
+        ubi::find_player_by_id(sessions, &player.to_lowercase()).await?
+    } else {
+        let platform = request.platform.as_deref().unwrap_or("uplay");
+        let platform = Platform::parse(platform).ok_or_else(|| UbiError::InvalidPlatform(platform.to_string()))?;
+        ubi::get_player_profiles_by_name(pool, sessions, platform, player, request.pick.as_deref())
+            .await?
+            .remove(0)
+    };
+
+    create_user(pool, &profile.id).await.map_err(internal)?;
+    if let Some(name) = &profile.name {
+        store_user_name(pool, &profile.id, profile.platform.as_str(), name).await.map_err(internal)?;
+    }
+    let game = division.as_str();
+    let added = add_tracked_player(pool, &profile.id, game).await.map_err(internal)?;
+    if added {
+        println!("Tracking {} stats of user {}", game, profile.id);
+    }
+    let tracked = list_tracked_players(pool)
+        .await?
+        .into_iter()
+        .find(|t| t.id == profile.id && t.game == game)
+        .ok_or_else(|| UbiError::Internal(format!("tracked player {} vanished", profile.id)))?;
+    Ok((tracked, added))
+}
+
+pub async fn untrack_player(pool: &Pool<Sqlite>, game: &str, id: &str) -> UbiResult<()> {
+    let division = parse_game(game)?;
+    if !ubi::is_valid_profile_id(id) {
+        return Err(UbiError::InvalidProfileId(id.to_string()));
+    }
+    let id = id.to_lowercase();
+    if !remove_tracked_player(pool, &id, division.as_str()).await.map_err(internal)? {
+        return Err(UbiError::NotTracked(id));
+    }
+    println!("Stopped tracking {} stats of user {}", division.as_str(), id);
+    Ok(())
+}
+
+/// Looks the tracked player up like `/api/divN/id/<id>` does, which stores the
+/// stats as a snapshot, and records how it went.
+async fn refresh_player(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, player: &TrackedPlayerDTO) -> UbiResult<()> {
+    let fetched = match Division::parse(&player.game) {
+        Some(Division::One) => ubi::get_div1_player_stats_by_id(pool, sessions, config, &player.id).await.map(|_| ()),
+        Some(Division::Two) => ubi::get_div2_player_stats_by_id(pool, sessions, config, &player.id, None).await.map(|_| ()),
+        None => Err(UbiError::UnknownGame(player.game.clone())),
+    };
+    let r = match &fetched {
+        Ok(()) => record_refresh(pool, &player.id, &player.game, ApiStatus::Ok.code(), None, None).await,
+        Err(e) => {
+            let r = ResponseWithStatus::from(e);
+            record_refresh(pool, &player.id, &player.game, r.status_code, r.response.error_code.as_deref(), Some(&r.response.message)).await
+        }
+    };
+    if let Err(e) = r {
+        println!("Failed to record refresh of tracked user {}: {:?}", player.id, e);
+    }
+    fetched
+}
+
+/// One pass over the tracked players, `upstream_concurrency` at a time. As
+/// soon as Ubisoft pushes back the pass stops, dropping lookups still in
+/// flight; whoever was left keeps their last outcome and comes first next time.
+pub async fn refresh_tracked_players(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig) -> UbiResult<()> {
+    let players = list_tracked_players(pool).await?;
+    // Not started yet: futures only run once the stream polls them.
+    let lookups: Vec<_> = players.iter().map(|p| refresh_player(pool, sessions, config, p)).collect();
+    let mut outcomes = futures::stream::iter(lookups).buffer_unordered(config.upstream_concurrency.max(1));
+    let mut refreshed = 0;
+    let mut failed = 0;
+    while let Some(outcome) = outcomes.next().await {
+        refreshed += 1;
+        match outcome {
+            Ok(()) => {}
+            Err(e @ (UbiError::RateLimited(_) | UbiError::UpstreamUnavailable(_) | UbiError::AuthFailed(_))) => {
+                failed += 1;
+                println!(
+                    "Stopping refresh of tracked players, {} left for the next pass: {}",
+                    players.len() - refreshed,
+                    e
+                );
+                break;
+            }
+            Err(_) => failed += 1,
+        }
+    }
+    println!("Refreshed {} tracked players, {} failed", refreshed, failed);
+    Ok(())
+}
+
+/// Refreshes the tracked players every `tracked_refresh_interval_secs`,
+/// starting one interval after launch so startup isn't slowed down.
+pub fn spawn_scheduler(pool: Pool<Sqlite>, sessions: UbiSessionPool, config: AppConfig) {
+    if config.tracked_refresh_interval_secs == 0 {
+        println!("Background refresh of tracked players is off");
+        return;
+    }
+    let interval = Duration::from_secs(config.tracked_refresh_interval_secs).max(MIN_REFRESH_INTERVAL);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = refresh_tracked_players(&pool, &sessions, &config).await {
+                println!("Background refresh of tracked players failed: {}", e);
+            }
+        }
+    });
+}
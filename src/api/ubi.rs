@@ -1,67 +1,37 @@
-use chrono::{DateTime, Utc};
-use lazy_static::lazy_static;
-use reqwest::{self, header::HeaderValue};
 use thirtyfour::prelude::*;
-use serde_json::{from_str, Value};
+use serde_json::{from_str, json, Value};
 use sqlx::{Pool, Sqlite};
-use std::sync::Mutex;
 use anyhow::anyhow;
 use futures::{future::join_all, StreamExt};
 use base64::Engine;
-
-use crate::db::user::{create_user, get_user_id_by_name, get_user_names_by_id, store_user_name};
-use crate::model::div::{D1PlayerStats, D2PlayerStats};
-use crate::model::ubi::{ProfileDTO, StatsDTO};
+use rocket::serde::Serialize;
+use std::collections::BTreeMap;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::api::error::{UbiError, UbiResult};
+use crate::api::session::UbiSessionPool;
+use crate::config::{AppConfig, UbiAccount};
+use crate::db::snapshot::store_snapshot;
+use crate::db::user::{create_user, get_name_holders, get_user_id_by_name, get_user_names_by_id, get_user_platform_by_id, store_user_name};
+use crate::model::div::{D1PlayerStats, D2PlayerStats, Div2Source, Division};
+use crate::model::game::{GameDefinition, GamePlayerStats};
+use crate::model::ubi::{CandidateDTO, Platform, ProfileDTO, SessionDTO, StatCard, StatsDTO, StoredPlayerDTO};
 use crate::util;
 
-lazy_static! {
-    static ref UBI_TICKET: Mutex<String> = Mutex::new("".to_string());
-    static ref UBI_SESSION_ID: Mutex<String> = Mutex::new("".to_string());
-    static ref UBI_EXPIRATION: Mutex<String> =
-        Mutex::new("2015-11-12T00:00:00.0000000Z".to_string());
-}
-
-pub async fn check_expiration_date() -> anyhow::Result<()> {
-    let expiration = UBI_EXPIRATION.lock().unwrap().clone();
-    let mut exp = DateTime::parse_from_rfc3339(&expiration)
-        .unwrap()
-        .with_timezone(&Utc);
-    let mut now = Utc::now() + chrono::Duration::minutes(5);
+const LOGIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
 
-    let mut login_counts = 0;
-    while exp < now && login_counts < 5 {
-        login_ubi().await?;
-        login_counts += 1;
-        println!("Renewed Ubi ticket at {}", now.to_rfc3339());
-        let expiration = UBI_EXPIRATION.lock().unwrap().clone();
-        exp = DateTime::parse_from_rfc3339(&expiration)
-            .unwrap()
-            .with_timezone(&Utc);
-        now = Utc::now() + chrono::Duration::minutes(5);
+async fn post_session(services_url: &str, authorization: String, two_factor_code: Option<&str>) -> anyhow::Result<Value> {
+    let mut headers = util::header::get_common_header(Platform::Uplay).await;
+    headers.insert("Authorization", authorization.parse()?);
+    if let Some(code) = two_factor_code {
+        headers.insert("Ubi-2faCode", code.parse()?);
     }
-    if login_counts >= 5 {
-        return Err(anyhow!("Failed to login after 5 trials"));
-    }
-    Ok(())
-}
-
-pub static UBI_LOGIN_URL: &str = "https://public-ubiservices.ubi.com/v3/profiles/sessions";
-pub async fn login_ubi() -> anyhow::Result<()> {
-    let mut headers = util::header::get_common_header().await;
-
-    let userpass = format!(
-        "{}:{}",
-        std::env::var("UBI_USERNAME").expect("UBI_USERNAME not set"),
-        std::env::var("UBI_PASSWORD").expect("UBI_PASSWORD not set")
-    );
-    let mut auth = String::new();
-    base64::engine::general_purpose::STANDARD.encode_string(userpass.as_bytes(), &mut auth);
-    headers.insert("Authorization", format!("Basic {}", auth).parse().unwrap());
-    auth.clear();
 
     let resp = reqwest::Client::new()
-        .post(UBI_LOGIN_URL)
+        .post(format!("{}/v3/profiles/sessions", services_url))
+        .timeout(LOGIN_TIMEOUT)
         .headers(headers)
+        .json(&json!({ "rememberMe": true }))
         .send()
         .await?
         .json::<Value>()
@@ -71,155 +41,335 @@ pub async fn login_ubi() -> anyhow::Result<()> {
         println!("{:#?}", resp);
         return Err(anyhow!("Failed to login to ubi"));
     }
+    Ok(resp)
+}
+
+/// Current code of the account's authenticator app, from its base32 TOTP secret.
+fn two_factor_code(account: &UbiAccount) -> anyhow::Result<String> {
+    let secret = account.totp_secret.as_ref().ok_or_else(|| {
+        anyhow!("Ubi account {} requires 2FA but has no TOTP secret", account.username)
+    })?;
+    let secret = Secret::Encoded(secret.replace(' ', "").to_uppercase())
+        .to_bytes()
+        .map_err(|e| anyhow!("TOTP secret of {} is not valid base32: {:?}", account.username, e))?;
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret);
+    Ok(totp.generate_current()?)
+}
 
-    let mut ticket = UBI_TICKET.lock().unwrap();
-    *ticket = resp["ticket"].as_str().unwrap().to_string();
+fn parse_session(resp: &Value, remember_me_ticket: Option<&str>) -> anyhow::Result<SessionDTO> {
+    match (resp["ticket"].as_str(), resp["sessionId"].as_str(), resp["expiration"].as_str()) {
+        (Some(ticket), Some(session_id), Some(expiration)) => Ok(SessionDTO {
+            ticket: ticket.to_string(),
+            session_id: session_id.to_string(),
+            expiration: expiration.to_string(),
+            // Ubisoft only hands out a new remember-me ticket now and then
+            remember_me_ticket: resp["rememberMeTicket"]
+                .as_str()
+                .or(remember_me_ticket)
+                .map(|t| t.to_string()),
+        }),
+        _ => Err(anyhow!("Ubi login response is missing ticket, sessionId or expiration")),
+    }
+}
 
-    let mut session_id = UBI_SESSION_ID.lock().unwrap();
-    *session_id = resp["sessionId"].as_str().unwrap().to_string();
+/// Logs in with the remember-me ticket of a previous login when there is one,
+/// otherwise with the password, answering the two-step verification challenge
+/// when the account has it enabled.
+pub async fn login_ubi(services_url: &str, account: &UbiAccount, remember_me_ticket: Option<&str>) -> anyhow::Result<SessionDTO> {
+    if let Some(rm_ticket) = remember_me_ticket {
+        match post_session(services_url, format!("rm_v1 t={}", rm_ticket), None).await {
+            Ok(resp) => return parse_session(&resp, remember_me_ticket),
+            Err(e) => println!("Failed to login with remember-me ticket, falling back to password: {}", e),
+        }
+    }
 
-    let mut expiration = UBI_EXPIRATION.lock().unwrap();
-    *expiration = resp["expiration"].as_str().unwrap().to_string();
+    let userpass = format!(
+        "{}:{}",
+        account.username,
+        account
+            .password
+            .as_ref()
+            .ok_or_else(|| anyhow!("No password set for Ubi account {}", account.username))?
+    );
+    let mut auth = String::new();
+    base64::engine::general_purpose::STANDARD.encode_string(userpass.as_bytes(), &mut auth);
+    let resp = post_session(services_url, format!("Basic {}", auth), None).await?;
+    auth.clear();
 
-    Ok(())
+    match resp["twoFactorAuthenticationTicket"].as_str() {
+        Some(two_factor_ticket) => {
+            let code = two_factor_code(account)?;
+            let resp = post_session(services_url, format!("ubi_2fa_v1 t={}", two_factor_ticket), Some(&code)).await?;
+            parse_session(&resp, None)
+        }
+        None => parse_session(&resp, None),
+    }
 }
 
 pub async fn find_player_id_by_db(
     pool: &Pool<Sqlite>,
+    platform: Platform,
     name: &str,
-) -> anyhow::Result<Vec<ProfileDTO>> {
-    let ids = get_user_id_by_name(pool, name)
+) -> UbiResult<Vec<ProfileDTO>> {
+    let ids = get_user_id_by_name(pool, platform.as_str(), name)
         .await
-        .map_err(|e| anyhow!("Failed to find player {} by db\nError: {}", name, e))?;
+        .map_err(|e| UbiError::Internal(format!("Failed to find player {} by db: {}", name, e)))?;
 
     let mut profiles = vec![];
     for id in ids {
-        profiles.push(ProfileDTO { id: id, name: None });
+        profiles.push(ProfileDTO { id, platform, name: None });
     }
     Ok(profiles)
 }
 
+/// What we already know about a name without asking Ubisoft, used while the
+/// upstream is unreachable.
+pub async fn get_stored_players_by_name(
+    pool: &Pool<Sqlite>,
+    platform: Platform,
+    name: &str,
+) -> UbiResult<Vec<StoredPlayerDTO>> {
+    let mut players = vec![];
+    for profile in find_player_id_by_db(pool, platform, name).await? {
+        let all_names = get_user_names_by_id(pool, &profile.id)
+            .await
+            .map_err(|e| UbiError::Internal(e.to_string()))?;
+        players.push(StoredPlayerDTO { id: profile.id, platform, all_names });
+    }
+    Ok(players)
+}
+
+pub async fn get_stored_player_by_id(
+    pool: &Pool<Sqlite>,
+    id: &str,
+) -> UbiResult<Vec<StoredPlayerDTO>> {
+    let platform = get_user_platform_by_id(pool, id)
+        .await
+        .map_err(|e| UbiError::Internal(e.to_string()))?;
+    let platform = match platform.as_deref().and_then(Platform::parse) {
+        Some(p) => p,
+        None => return Ok(vec![]),
+    };
+    let all_names = get_user_names_by_id(pool, id)
+        .await
+        .map_err(|e| UbiError::Internal(e.to_string()))?;
+    Ok(vec![StoredPlayerDTO { id: id.to_string(), platform, all_names }])
+}
+
 pub async fn find_player_id_by_api(
+    sessions: &UbiSessionPool,
+    platform: Platform,
     name: Option<&str>,
     id: Option<&str>
-) -> anyhow::Result<Vec<ProfileDTO>> {
+) -> UbiResult<Vec<ProfileDTO>> {
     if name.is_none() && id.is_none() {
-        return Err(anyhow!("Both name and id are None"));
+        return Err(UbiError::Internal("Both name and id are None".to_string()));
     }
-    if let Err(e) = check_expiration_date().await {
-        return Err(anyhow!(e))
-    }
-
-    let ticket = UBI_TICKET.lock().unwrap().clone();
-    let mut headers = util::header::get_common_header().await;
-    headers.insert(
-        "Authorization",
-        format!("Ubi_v1 t={}", &*ticket).parse().unwrap(),
-    );
 
-    let session_id = UBI_SESSION_ID.lock().unwrap().clone();
-    headers.insert(
-        "Ubi-SessionId",
-        (*session_id).parse::<HeaderValue>().unwrap(),
-    );
-
-    let mut url = String::from("https://public-ubiservices.ubi.com/v2/profiles?platformType=uplay&");
-    if name.is_some() {
-        url.push_str(&format!(
-            "nameOnPlatform={}",
-            name.unwrap()
-        ));
-    } else {
-        url.push_str(&format!("idOnPlatform={}", id.unwrap()));
+    let mut url = format!("{}/v2/profiles?platformType={}&", sessions.services_url(), platform);
+    if let Some(name) = name {
+        url.push_str(&format!("nameOnPlatform={}", name));
+    } else if let Some(id) = id {
+        url.push_str(&format!("idOnPlatform={}", id));
     }
 
-    let resp = reqwest::Client::new()
-        .get(&url)
-        .headers(headers)
-        .send()
-        .await?
-        .json::<Value>()
-        .await?;
+    let resp = sessions.get_json(&url, platform).await?;
 
-    let profiles = &resp["profiles"];
-    if profiles.is_array() && profiles.as_array().unwrap().is_empty() {
-        return Err(anyhow!("Failed to find player with name: {} id: {} by api", name.clone().unwrap_or(""), id.clone().unwrap_or("")));
+    let profiles = resp["profiles"]
+        .as_array()
+        .ok_or_else(|| UbiError::ParseError("profiles is not an array".to_string()))?;
+    if profiles.is_empty() {
+        return Err(UbiError::PlayerNotFound(name.or(id).unwrap_or("").to_string()));
     }
 
-    Ok(profiles
-        .as_array()
-        .unwrap()
-        .into_iter()
-        .map(|p| ProfileDTO {
-            id: p["profileId"].as_str().unwrap().to_string(),
-            name: Some(p["nameOnPlatform"].as_str().unwrap().to_string()),
+    profiles
+        .iter()
+        .map(|p| match (p["profileId"].as_str(), p["nameOnPlatform"].as_str()) {
+            (Some(id), Some(name)) => Ok(ProfileDTO {
+                id: id.to_string(),
+                platform,
+                name: Some(name.to_string()),
+            }),
+            _ => Err(UbiError::ParseError("profile without profileId or nameOnPlatform".to_string())),
         })
-        .collect::<Vec<ProfileDTO>>())
+        .collect::<UbiResult<Vec<ProfileDTO>>>()
 }
 
+/// Resolves a name to exactly one profile. Names get released and reused, so
+/// when several stored players have held it the caller must `pick` one of them.
 pub async fn get_player_profiles_by_name(
     pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    platform: Platform,
     name: &str,
-) -> anyhow::Result<Vec<ProfileDTO>> {
-    let mut profiles = find_player_id_by_api(Some(name), None).await.unwrap_or(vec![]);
+    pick: Option<&str>,
+) -> UbiResult<Vec<ProfileDTO>> {
+    let profiles = match find_player_id_by_api(sessions, platform, Some(name), None).await {
+        Ok(profiles) => profiles,
+        Err(api_error) => {
+            // The name may have changed since we last saw it
+            let profiles = find_player_id_by_db(pool, platform, name).await?;
+            if profiles.is_empty() {
+                return Err(match api_error {
+                    UbiError::PlayerNotFound(_) => UbiError::PlayerNotFound(name.to_string()),
+                    e => e,
+                });
+            }
+            profiles
+        }
+    };
+    pick_profile(pool, platform, name, profiles, pick).await
+}
 
-    if profiles.is_empty() {
-        profiles = find_player_id_by_db(pool, name).await?;
+/// Narrows the profiles found for `name` down to the one to use: the `pick`ed
+/// one, or the only one, otherwise the caller gets the candidates to pick from.
+pub async fn pick_profile(
+    pool: &Pool<Sqlite>,
+    platform: Platform,
+    name: &str,
+    profiles: Vec<ProfileDTO>,
+    pick: Option<&str>,
+) -> UbiResult<Vec<ProfileDTO>> {
+    if let Some(pick) = pick {
+        if !is_valid_profile_id(pick) {
+            return Err(UbiError::InvalidProfileId(pick.to_string()));
+        }
+        return match profiles.into_iter().find(|p| p.id.eq_ignore_ascii_case(pick)) {
+            Some(p) => Ok(vec![p]),
+            None => Err(UbiError::InvalidRequest(format!("{} is not a match for {}", pick, name))),
+        };
+    }
+    if profiles.len() > 1 {
+        return Err(UbiError::MultipleMatches(name.to_string(), get_candidates(pool, platform, name, &profiles).await?));
     }
     Ok(profiles)
 }
 
-pub async fn get_player_stats_by_name(
+async fn get_candidates(
     pool: &Pool<Sqlite>,
+    platform: Platform,
     name: &str,
-    game_space_id: &str,
-) -> anyhow::Result<Vec<StatsDTO>> {
-    if let Err(e) = check_expiration_date().await {
-        return Err(anyhow!(e))
+    profiles: &[ProfileDTO],
+) -> UbiResult<Vec<CandidateDTO>> {
+    let holders = get_name_holders(pool, platform.as_str(), name)
+        .await
+        .map_err(|e| UbiError::Internal(format!("Failed to get holders of {}: {}", name, e)))?;
+    Ok(profiles
+        .iter()
+        .map(|p| {
+            let holder = holders.iter().find(|h| h.id == p.id);
+            CandidateDTO {
+                id: p.id.clone(),
+                platform: p.platform,
+                current_name: p
+                    .name
+                    .clone()
+                    .or_else(|| holder.map(|h| h.current_name.clone()))
+                    .unwrap_or_default(),
+                held_from: holder.map(|h| h.first_seen.clone()),
+                held_until: holder.map(|h| h.last_seen.clone()),
+            }
+        })
+        .collect())
+}
+
+pub fn is_valid_profile_id(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Looks a profile up by its id, which unlike the name never changes.
+pub async fn find_player_by_id(sessions: &UbiSessionPool, id: &str) -> UbiResult<ProfileDTO> {
+    let url = format!("{}/v2/profiles?profileId={}", sessions.services_url(), id);
+    let resp = sessions.get_json(&url, Platform::Uplay).await?;
+    let p = &resp["profiles"][0];
+    if p.is_null() {
+        return Err(UbiError::PlayerNotFound(id.to_string()));
     }
+    let platform = p["platformType"]
+        .as_str()
+        .and_then(Platform::parse)
+        .ok_or_else(|| UbiError::ParseError(format!("unknown platformType {} of profile {}", p["platformType"], id)))?;
+    Ok(ProfileDTO {
+        id: id.to_lowercase(),
+        platform,
+        name: p["nameOnPlatform"].as_str().map(|n| n.to_string()),
+    })
+}
 
-    let mut headers = util::header::get_common_header().await;
-    let ticket = UBI_TICKET.lock().unwrap().clone();
-    headers.insert(
-        "Authorization",
-        format!("Ubi_v1 t={}", &ticket).parse().unwrap(),
-    );
+/// Current name of a profile found in the database. When Ubisoft can't tell
+/// (e.g. in degraded mode) the name it was last stored with, rather than
+/// failing the lookup.
+async fn resolve_stored_name(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, id: &str) -> Option<String> {
+    match find_player_by_id(sessions, id).await {
+        Ok(ProfileDTO { name: Some(name), .. }) => return Some(name),
+        Ok(_) => println!("Ubisoft has no name for user {}", id),
+        Err(e) => println!("Failed to get name for user {}: {}", id, e),
+    }
+    get_user_names_by_id(pool, id).await.ok().and_then(|names| names.into_iter().next())
+}
 
-    let session_id = UBI_SESSION_ID.lock().unwrap().clone();
-    headers.insert(
-        "Ubi-SessionId",
-        (*session_id).parse::<HeaderValue>().unwrap(),
-    );
+pub async fn get_player_stats_by_name(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    platform: Platform,
+    name: &str,
+    pick: Option<&str>,
+    game_space_id: &str,
+) -> UbiResult<Vec<StatsDTO>> {
+    let profiles = get_player_profiles_by_name(pool, sessions, platform, name, pick).await?;
+    get_player_stats_by_profiles(pool, sessions, config, profiles, name, game_space_id).await
+}
 
-    let mut profiles = get_player_profiles_by_name(pool, name).await?;
+/// The cards of a `/v1/profiles/<id>/statscard` response.
+fn parse_statscards(resp: &Value) -> UbiResult<Vec<StatCard>> {
+    if !resp["Statscards"].is_array() {
+        return Err(UbiError::ParseError("Statscards is not an array".to_string()));
+    }
+    Ok(serde_json::from_value(resp["Statscards"].clone())?)
+}
 
+/// Statscards of already resolved profiles, recording their current names.
+/// `key` is the name or id the caller asked for, used in errors.
+pub async fn get_player_stats_by_profiles(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    mut profiles: Vec<ProfileDTO>,
+    key: &str,
+    game_space_id: &str,
+) -> UbiResult<Vec<StatsDTO>> {
     let mut results: Vec<StatsDTO> = vec![];
     let urls = profiles
         .iter()
         .map(|p| {
             let url = format!(
-                "https://public-ubiservices.ubi.com/v1/profiles/{}/statscard?spaceId={}",
-                p.id, game_space_id
+                "{}/v1/profiles/{}/statscard?spaceId={}",
+                sessions.services_url(), p.id, game_space_id
             );
-            url
+            (url, p.platform)
         })
-        .collect::<Vec<String>>();
+        .collect::<Vec<_>>();
 
-    let client = reqwest::Client::new();
-    let stream =
-        futures::stream::iter(urls).map(|url| client.get(&url).headers(headers.clone()).send());
+    let stream = futures::stream::iter(urls)
+        .map(|(url, platform)| async move { sessions.get_json(&url, platform).await });
 
-    let mut stream = stream.buffered(5);
+    let mut stream = stream.buffered(config.upstream_concurrency);
 
     let mut i = 0;
     while let Some(result) = stream.next().await {
-        let resp = result?.json::<Value>().await?;
-        if !resp["errorCode"].is_null() {
-            println!("{:#?}", resp);
-            return Err(anyhow!("Failed to get stats for user {}", &profiles[i].id));
-        }
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(UbiError::PlayerNotFound(_)) => return Err(UbiError::NoGameProfile(key.to_string())),
+            Err(e) => return Err(e),
+        };
         let profile = &mut profiles[i];
-        match create_user(&pool, &profile.id).await {
+        let platform = profile.platform;
+        match create_user(pool, &profile.id).await {
             Ok(_) => println!("Created or update user {}", &profile.id),
             Err(e) => {
                 println!("Failed to create or update user {}: {:?}", &profile.id, e)
@@ -228,27 +378,13 @@ pub async fn get_player_stats_by_name(
         let name = match &profile.name {
             Some(n) => (*n).clone(),
             None => {
-                println!("Failed to get name for user {}", &profile.id);
-                let url = format!(
-                    "https://public-ubiservices.ubi.com/v2/profiles?userId={}&platformType=uplay",
-                    &profile.id
-                );
-                let res = reqwest::Client::new()
-                    .get(&url)
-                    .headers(headers.clone())
-                    .send()
-                    .await?
-                    .json::<Value>()
-                        .await?["profiles"][0]["nameOnPlatform"]
-                        .as_str()
-                        .unwrap()
-                        .to_string();
+                let res = resolve_stored_name(pool, sessions, &profile.id).await.unwrap_or_default();
                 profile.name = Some(res.clone());
                 res
             }
         };
 
-        match store_user_name(&pool, &profile.id, &name).await {
+        match store_user_name(pool, &profile.id, platform.as_str(), &name).await {
             Ok(_) => println!("Stored name {} for user {}", &name, &profile.id),
             Err(e) => {
                 println!(
@@ -258,71 +394,263 @@ pub async fn get_player_stats_by_name(
             }
         }
         results.push(StatsDTO {
-            stats: resp["Statscards"].as_array().unwrap().clone(),
+            stats: parse_statscards(&resp)?,
             profile: profile.clone(),
         });
         i += 1;
     }
 
     if results.is_empty() {
-        return Err(anyhow!("Failed to find player {} by either api or db", name));
+        return Err(UbiError::PlayerNotFound(key.to_string()));
     }
     Ok(results)
 }
 
-pub static DIV1_SPACE_ID: &str = "6edd234a-abff-4e90-9aab-b9b9c6e49ff7";
+/// `source` of snapshots built from Ubisoft statscards, same as `Div2Source::Ubi`.
+pub const STATSCARD_SOURCE: &str = "ubi";
+
+/// Fetched `stats` of a player as they are snapshotted, minus who they belong
+/// to (`user_names` tracks that), where they came from and what is derived
+/// from them.
+pub fn snapshot_stats(stats: &impl Serialize) -> Value {
+    let mut stats = json!(stats);
+    if let Some(fields) = stats.as_object_mut() {
+        for key in ["name", "platform", "source", "game", "derived", "all_names"] {
+            fields.remove(key);
+        }
+    }
+    stats
+}
+
+/// Keeps the fetched `stats` of a player as a snapshot. Failing to store
+/// doesn't fail the lookup.
+async fn record_snapshot(pool: &Pool<Sqlite>, id: &str, game: &str, source: &str, stats: &impl Serialize) {
+    match store_snapshot(pool, id, game, source, &snapshot_stats(stats)).await {
+        Ok(true) => println!("Stored {} snapshot of user {}", game, id),
+        Ok(false) => println!("{} stats of user {} unchanged since last snapshot", game, id),
+        Err(e) => println!("Failed to store {} snapshot of user {}: {:?}", game, id, e),
+    }
+}
+
 pub async fn get_div1_player_stats(
     pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    platform: Platform,
+    name: &str,
+    pick: Option<&str>,
+) -> UbiResult<Vec<D1PlayerStats>> {
+    let res = get_player_stats_by_name(pool, sessions, config, platform, name, pick, &config.div1_space_id).await?;
+    to_div1_player_stats(pool, res).await
+}
+
+pub async fn get_div1_player_stats_by_id(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    id: &str,
+) -> UbiResult<Vec<D1PlayerStats>> {
+    let profile = find_player_by_id(sessions, id).await?;
+    let res = get_player_stats_by_profiles(pool, sessions, config, vec![profile], id, &config.div1_space_id).await?;
+    to_div1_player_stats(pool, res).await
+}
+
+/// statNames of the Division 1 statscards mapped to `D1PlayerStats` fields, with
+/// the position each card had in the responses the stats were first read by.
+/// The names aren't confirmed against a recorded response yet, so a card that
+/// isn't found by name is read at its old position. Cards 9 and 10 were never
+/// read by position, so headshot and NPC kills only come by name.
+const DIV1_STATS: &[(&str, Option<usize>)] = &[
+    ("playerLevel", Some(0)),
+    ("rankDZ", Some(1)),
+    ("rankUG", Some(2)),
+    ("timePlayed", Some(3)),
+    ("mainStoryProgress", Some(4)),
+    ("roguesKilled", Some(5)),
+    ("itemsExtracted", Some(6)),
+    ("killsSkill", Some(7)),
+    ("killsTotal", Some(8)),
+    ("killsHeadshot", None),
+    ("killsNpc", None),
+    ("gearScore", Some(11)),
+];
+
+/// Cards a response needs for every position of `DIV1_STATS` to be read.
+const DIV1_POSITIONAL_CARDS: usize = 12;
+
+fn is_div1_stat(card: &StatCard) -> bool {
+    DIV1_STATS.iter().any(|(name, _)| *name == card.stat_name)
+}
+
+/// The Division 1 stats on the statscards, without `all_names` and `derived`.
+fn div1_stats(p: ProfileDTO, cards: Vec<StatCard>) -> D1PlayerStats {
+    let mut cards = cards.into_iter().map(Some).collect::<Vec<_>>();
+    // By name, else at the old position unless the card there has a known name.
+    let mut stat = |name: &str| {
+        if let Some(card) = cards.iter_mut().find(|c| c.as_ref().is_some_and(|c| c.stat_name == name)) {
+            return card.take();
+        }
+        let position = DIV1_STATS.iter().find(|(n, _)| *n == name).and_then(|(_, position)| *position)?;
+        let card = cards.get_mut(position).filter(|c| c.as_ref().is_some_and(|c| !is_div1_stat(c)))?.take();
+        if let Some(c) = &card {
+            println!("No Division 1 statscard {}, reading card {} ({}) instead", name, position, c.stat_name);
+        }
+        card
+    };
+    let number = |card: Option<StatCard>| card.map(|c| c.as_u64()).unwrap_or(0);
+    let main_story = stat("mainStoryProgress");
+    let playtime_secs = number(stat("timePlayed"));
+
+    D1PlayerStats {
+        id: p.id,
+        name: p.name.unwrap_or("".to_string()),
+        platform: p.platform,
+        level: number(stat("playerLevel")),
+        dz_rank: number(stat("rankDZ")),
+        ug_rank: number(stat("rankUG")),
+        playtime: playtime_secs / 3600,
+        playtime_secs,
+        main_story: main_story
+            .as_ref()
+            .and_then(|c| c.value.as_str().map(|s| s.to_string()))
+            .unwrap_or("0 %".to_string()),
+        main_story_percent: main_story.and_then(|c| c.as_f64()).unwrap_or(0.0),
+        rogue_kills: number(stat("roguesKilled")),
+        items_extracted: number(stat("itemsExtracted")),
+        skill_kills: number(stat("killsSkill")),
+        total_kills: number(stat("killsTotal")),
+        headshot_kills: number(stat("killsHeadshot")),
+        npc_kills: number(stat("killsNpc")),
+        gear_score: number(stat("gearScore")),
+        extra: cards
+            .into_iter()
+            .flatten()
+            .map(|c| (c.stat_name.clone(), c.to_json()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+async fn to_div1_player_stats(pool: &Pool<Sqlite>, res: Vec<StatsDTO>) -> UbiResult<Vec<D1PlayerStats>> {
+    if let Some(r) = res.iter().find(|r| r.stats.len() < DIV1_POSITIONAL_CARDS && !r.stats.iter().any(is_div1_stat)) {
+        return Err(UbiError::ParseError(format!("no known statscard for user {}", r.profile.id)));
+    }
+    let stats = join_all(
+        res.into_iter()
+            .map(|r| async move {
+                let all_names = get_user_names_by_id(pool, &r.profile.id).await.unwrap_or(vec![]);
+                D1PlayerStats { all_names, ..div1_stats(r.profile, r.stats) }.with_derived()
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await;
+    for s in &stats {
+        record_snapshot(pool, &s.id, Division::One.as_str(), STATSCARD_SOURCE, s).await;
+    }
+    Ok(stats)
+}
+
+pub async fn get_game_player_stats(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    game: &GameDefinition,
+    platform: Platform,
     name: &str,
-) -> anyhow::Result<Vec<D1PlayerStats>> {
-    let res = get_player_stats_by_name(pool, name, DIV1_SPACE_ID).await?;
-    Ok(join_all(
+    pick: Option<&str>,
+) -> UbiResult<Vec<GamePlayerStats>> {
+    let res = get_player_stats_by_name(pool, sessions, config, platform, name, pick, &game.space_id).await?;
+    to_game_player_stats(pool, game, name, res).await
+}
+
+pub async fn get_game_player_stats_by_id(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    game: &GameDefinition,
+    id: &str,
+) -> UbiResult<Vec<GamePlayerStats>> {
+    let profile = find_player_by_id(sessions, id).await?;
+    let res = get_player_stats_by_profiles(pool, sessions, config, vec![profile], id, &game.space_id).await?;
+    to_game_player_stats(pool, game, id, res).await
+}
+
+/// Statscards renamed to the fields the game's config maps them to.
+async fn to_game_player_stats(pool: &Pool<Sqlite>, game: &GameDefinition, key: &str, res: Vec<StatsDTO>) -> UbiResult<Vec<GamePlayerStats>> {
+    if res.iter().all(|r| r.stats.is_empty()) {
+        return Err(UbiError::NoGameProfile(key.to_string()));
+    }
+    let stats = join_all(
         res.into_iter()
             .map(|r| async move {
                 let p = r.profile;
-                let s = r.stats;
-                D1PlayerStats {
+                let mut stats = BTreeMap::new();
+                let mut extra = BTreeMap::new();
+                for card in r.stats {
+                    match game.stats.get(&card.stat_name) {
+                        Some(f) => {
+                            stats.insert(f.field().to_string(), card.scaled_json(f.divisor()));
+                        }
+                        None => {
+                            extra.insert(card.stat_name.clone(), card.to_json());
+                        }
+                    }
+                }
+                GamePlayerStats {
                     id: p.id.clone(),
                     name: p.name.unwrap_or("".to_string()),
-                    level: s[0]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    dz_rank: s[1]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    ug_rank: s[2]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    playtime: s[3]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0) / 3600,
-                    main_story: s[4]["value"].as_str().unwrap_or("0 %").to_string(),
-                    rogue_kills: s[5]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    items_extracted: s[6]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    skill_kills: s[7]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    total_kills: s[8]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    gear_score: s[11]["value"].as_str().unwrap().parse::<u64>().unwrap_or(0),
-                    all_names: get_user_names_by_id(pool, p.id.clone().as_str())
+                    platform: p.platform,
+                    game: game.slug.clone(),
+                    stats,
+                    extra,
+                    all_names: get_user_names_by_id(pool, p.id.as_str())
                         .await
                         .unwrap_or(vec![]),
                 }
             })
             .collect::<Vec<_>>(),
     )
-    .await)
+    .await;
+    // flattened like the div1/div2 snapshots: mapped fields at the top, `extra` beside them
+    let snapshot_game = format!("games/{}", game.slug);
+    for s in &stats {
+        let mut snapshot = s.stats.clone();
+        snapshot.insert("extra".to_string(), json!(s.extra));
+        record_snapshot(pool, &s.id, &snapshot_game, STATSCARD_SOURCE, &snapshot).await;
+    }
+    Ok(stats)
+}
+
+async fn get_tracker_profile(config: &AppConfig, platform: Platform, name: &str) -> UbiResult<Value> {
+    let url = format!("{}/api/v2/division-2/standard/profile/{}/{}", config.tracker_url, platform, name);
+    if !config.tracker_use_browser {
+        return Ok(reqwest::get(&url).await?.json::<Value>().await?);
+    }
+
+    let driver = util::webdriver::get_webdriver(config.chrome_port).await?;
+    driver.goto(url).await?;
+    let data = driver.find(By::Css("body")).await?.text().await?;
+    driver.quit().await?;
+    Ok(from_str(&data)?)
 }
 
-// pub static DIV2_SPACE_ID: &str = "60859c37-949d-49e2-8fc8-6d8dc40f1a9e";
-pub static TRACKER_URL: &str = "https://api.tracker.gg/api/v2/division-2/standard/profile/uplay/";
 pub async fn get_div2_player_stats(
     pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    platform: Platform,
     name: &str,
-) -> anyhow::Result<Vec<D2PlayerStats>> {
-    let mut profiles = find_player_id_by_api(Some(name), None).await.unwrap_or(vec![]);
-
-    if profiles.is_empty() {
-        profiles = find_player_id_by_db(pool, &name).await?;
-        if profiles.is_empty() {
-            return Err(anyhow!("Failed to find player {} by either api or db", name));
-        }
-        for mut profile in profiles.iter_mut() {
-            profile.name = find_player_id_by_api(None, Some(&profile.id)).await?[0].name.clone();
+    pick: Option<&str>,
+    source: Option<Div2Source>,
+) -> UbiResult<Vec<D2PlayerStats>> {
+    let mut profiles = get_player_profiles_by_name(pool, sessions, platform, name, pick).await?;
+
+    if profiles[0].name.is_none() {
+        for profile in profiles.iter_mut() {
+            profile.name = resolve_stored_name(pool, sessions, &profile.id).await.or_else(|| Some(name.to_string()));
         }
-
     } else {
-        match create_user(&pool, &profiles[0].id).await {
+        match create_user(pool, &profiles[0].id).await {
             Ok(_) => println!("Created or update user {}", &profiles[0].id),
             Err(e) => {
                 println!("Failed to create or update user {}: {:?}", &profiles[0].id, e)
@@ -330,63 +658,297 @@ pub async fn get_div2_player_stats(
         }
     }
 
-    let p = &profiles[0];
+    get_div2_stats_of_profile(pool, sessions, config, &profiles[0], name, source).await
+}
+
+pub async fn get_div2_player_stats_by_id(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    id: &str,
+    source: Option<Div2Source>,
+) -> UbiResult<Vec<D2PlayerStats>> {
+    let profile = find_player_by_id(sessions, id).await?;
+    match create_user(pool, &profile.id).await {
+        Ok(_) => println!("Created or update user {}", &profile.id),
+        Err(e) => {
+            println!("Failed to create or update user {}: {:?}", &profile.id, e)
+        }
+    }
+    get_div2_stats_of_profile(pool, sessions, config, &profile, id, source).await
+}
+
+/// Stats of a resolved profile from `source`, recording its current name.
+/// Without a source tracker.gg is asked first and the statscards are used when
+/// it can't be reached. `key` is the name or id the caller asked for, used in errors.
+async fn get_div2_stats_of_profile(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    p: &ProfileDTO,
+    key: &str,
+    source: Option<Div2Source>,
+) -> UbiResult<Vec<D2PlayerStats>> {
     let p_name = p.name.clone().unwrap_or("".to_string());
-    match store_user_name(&pool, &p.id, &p_name).await {
+    match store_user_name(pool, &p.id, p.platform.as_str(), &p_name).await {
         Ok(_) => println!("Stored name {} for user {}", &p_name, &p.id),
         Err(e) => {
             println!(
                 "Failed to store name {} for user {}: {:?}",
-                &name, &p.id, e
+                &p_name, &p.id, e
             );
         }
     }
 
-    let driver = util::webdriver::get_webdriver().await?;
-    driver.goto(format!("{}{}", TRACKER_URL, p.name.clone().unwrap_or("".to_string()))).await.unwrap();
-    let data = driver.find(By::Css("body")).await?.text().await?;
-    driver.quit().await?;
+    let stats = match source {
+        Some(Div2Source::Ubi) => get_statscard_div2_stats(pool, sessions, config, p, key).await,
+        Some(Div2Source::Tracker) => get_tracker_div2_stats(pool, config, p, key).await,
+        None => match get_tracker_div2_stats(pool, config, p, key).await {
+            Err(e @ (UbiError::UpstreamUnavailable(_) | UbiError::ParseError(_))) => {
+                println!("tracker.gg failed for {}, falling back to statscards: {}", key, e);
+                get_statscard_div2_stats(pool, sessions, config, p, key).await
+            }
+            r => r,
+        },
+    }?;
+    let stats: Vec<D2PlayerStats> = stats.into_iter().map(D2PlayerStats::with_derived).collect();
+    for s in &stats {
+        record_snapshot(pool, &s.id, Division::Two.as_str(), s.source.as_str(), s).await;
+    }
+    Ok(stats)
+}
 
-    let metadata: Value = from_str(&data)?;
+async fn get_tracker_div2_stats(
+    pool: &Pool<Sqlite>,
+    config: &AppConfig,
+    p: &ProfileDTO,
+    key: &str,
+) -> UbiResult<Vec<D2PlayerStats>> {
+    let platform = p.platform;
+    let p_name = p.name.clone().unwrap_or("".to_string());
+    let metadata = get_tracker_profile(config, platform, &p_name).await?;
     let stats = &metadata["data"]["segments"][0]["stats"];
     
     if stats.is_null() {
-        return Err(anyhow!("player {} exists but no profile for this game", name));
+        return Err(UbiError::NoGameProfile(key.to_string()));
     }
     Ok(vec![D2PlayerStats {
         id: p.id.clone(),
         name: p.name.clone().unwrap_or("".to_string()),
+        platform,
+        source: Div2Source::Tracker,
         total_playtime: stats["timePlayed"]["value"].as_u64().unwrap_or(0) / 3600,
+        total_playtime_secs: stats["timePlayed"]["value"].as_u64().unwrap_or(0),
         level: stats["highestPlayerLevel"]["value"].as_u64().unwrap_or(0),
         pvp_kills: stats["killsPvP"]["value"].as_u64().unwrap_or(0),
         npc_kills: stats["killsNpc"]["value"].as_u64().unwrap_or(0),
         headshots: stats["headshots"]["value"].as_u64().unwrap_or(0),
         headshot_kills: stats["killsHeadshot"]["value"].as_u64().unwrap_or(0),
-        shotgun_kills: stats["killsWeaponShotgun"]["value"].as_u64().unwrap_or(0),
-        smg_kills: stats["killsWeaponSubMachinegun"]["value"].as_u64().unwrap_or(0),
-        pistol_kills: stats["killsWeaponPistol"]["value"].as_u64().unwrap_or(0),
-        rifle_kills: stats["killsWeaponRifle"]["value"].as_u64().unwrap_or(0),
+        shotgun_kills: stats["killsWeaponShotgun"]["value"].as_u64(),
+        smg_kills: stats["killsWeaponSubMachinegun"]["value"].as_u64(),
+        pistol_kills: stats["killsWeaponPistol"]["value"].as_u64(),
+        rifle_kills: stats["killsWeaponRifle"]["value"].as_u64(),
         player_kills: stats["playersKilled"]["value"].as_u64().unwrap_or(0),
         xp_total: stats["xPTotal"]["value"].as_u64().unwrap_or(0),
-        pve_xp: stats["xPPve"]["value"].as_u64().unwrap_or(0),
-        pvp_xp: stats["xPPvp"]["value"].as_u64().unwrap_or(0),
-        clan_xp: stats["xPClan"]["value"].as_u64().unwrap_or(0),
-        sharpshooter_kills: stats["killsSpecializationSharpshooter"]["value"].as_u64().unwrap_or(0),
-        survivalist_kills: stats["killsSpecializationSurvivalist"]["value"].as_u64().unwrap_or(0),
-        demolitionist_kills: stats["killsSpecializationDemolitionist"]["value"].as_u64().unwrap_or(0),
+        pve_xp: stats["xPPve"]["value"].as_u64(),
+        pvp_xp: stats["xPPvp"]["value"].as_u64(),
+        clan_xp: stats["xPClan"]["value"].as_u64(),
+        sharpshooter_kills: stats["killsSpecializationSharpshooter"]["value"].as_u64(),
+        survivalist_kills: stats["killsSpecializationSurvivalist"]["value"].as_u64(),
+        demolitionist_kills: stats["killsSpecializationDemolitionist"]["value"].as_u64(),
         e_credit: stats["eCreditBalance"]["value"].as_u64().unwrap_or(0),
-        commendation_count: stats["commendationCount"]["value"].as_u64().unwrap_or(0),
+        commendation_count: stats["commendationCount"]["value"].as_u64(),
         commendation_score: stats["commendationScore"]["value"].as_u64().unwrap_or(0),
         gear_score: stats["latestGearScore"]["value"].as_u64().unwrap_or(0),
         dz_rank: stats["rankDZ"]["value"].as_u64().unwrap_or(0),
         dz_playtime: stats["timePlayedDarkZone"]["value"].as_u64().unwrap_or(0) / 3600,
         rogues_killed: stats["roguesKilled"]["value"].as_u64().unwrap_or(0),
-        rogue_playtime: stats["timePlayedRogue"]["value"].as_u64().unwrap_or(0) / 3600,
-        longest_rogue: stats["timePlayedRogueLongest"]["value"].as_u64().unwrap_or(0) / 60,
+        rogue_playtime: stats["timePlayedRogue"]["value"].as_u64().map(|v| v / 3600),
+        longest_rogue: stats["timePlayedRogueLongest"]["value"].as_u64().map(|v| v / 60),
         conflict_rank: stats["latestConflictRank"]["value"].as_u64().unwrap_or(0),
         conflict_playtime: stats["timePlayedConflict"]["value"].as_u64().unwrap_or(0) / 3600,
-        all_names: get_user_names_by_id(pool, profiles[0].id.clone().as_str())
+        extra: BTreeMap::new(),
+        all_names: get_user_names_by_id(pool, p.id.clone().as_str())
+            .await
+            .unwrap_or(vec![]),
+        ..Default::default()
+    }])
+}
+
+async fn fetch_statscards(sessions: &UbiSessionPool, p: &ProfileDTO, key: &str, game_space_id: &str) -> UbiResult<Vec<StatCard>> {
+    let url = format!(
+        "{}/v1/profiles/{}/statscard?spaceId={}",
+        sessions.services_url(), p.id, game_space_id
+    );
+    let resp = match sessions.get_json(&url, p.platform).await {
+        Ok(resp) => resp,
+        Err(UbiError::PlayerNotFound(_)) => return Err(UbiError::NoGameProfile(key.to_string())),
+        Err(e) => return Err(e),
+    };
+    parse_statscards(&resp)
+}
+
+/// The Division 2 from Ubisoft statscards, no browser needed. Only the fields
+/// listed in the README are filled; the ones only tracker.gg has stay `None`
+/// and are left out of the response.
+async fn get_statscard_div2_stats(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    p: &ProfileDTO,
+    key: &str,
+) -> UbiResult<Vec<D2PlayerStats>> {
+    let cards = fetch_statscards(sessions, p, key, &config.div2_space_id).await?;
+    if cards.is_empty() {
+        return Err(UbiError::NoGameProfile(key.to_string()));
+    }
+    let mut cards = cards
+        .into_iter()
+        .map(|c| (c.stat_name.clone(), c))
+        .collect::<BTreeMap<_, _>>();
+    let mut stat = |name: &str| cards.remove(name).map(|c| c.as_u64()).unwrap_or(0);
+    let total_playtime_secs = stat("timePlayed");
+
+    Ok(vec![D2PlayerStats {
+        id: p.id.clone(),
+        name: p.name.clone().unwrap_or("".to_string()),
+        platform: p.platform,
+        source: Div2Source::Ubi,
+        total_playtime: total_playtime_secs / 3600,
+        total_playtime_secs,
+        level: stat("playerLevel"),
+        pvp_kills: stat("killsPvP"),
+        npc_kills: stat("killsNpc"),
+        headshots: stat("headshots"),
+        headshot_kills: stat("killsHeadshot"),
+        player_kills: stat("playersKilled"),
+        xp_total: stat("xPTotal"),
+        e_credit: stat("eCreditBalance"),
+        commendation_score: stat("commendationScore"),
+        gear_score: stat("gearScore"),
+        dz_rank: stat("rankDZ"),
+        dz_playtime: stat("timePlayedDarkZone") / 3600,
+        rogues_killed: stat("roguesKilled"),
+        conflict_rank: stat("conflictRank"),
+        conflict_playtime: stat("timePlayedConflict") / 3600,
+        extra: cards.into_iter().map(|(name, c)| (name, c.to_json())).collect(),
+        all_names: get_user_names_by_id(pool, p.id.as_str())
             .await
-            .unwrap_or(vec![])
+            .unwrap_or(vec![]),
+        // Statscards don't have the rest, which stays `None` and unserialized.
+        ..Default::default()
     }])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written by hand in the shape of a statscard response for The Division,
+    /// with a made-up player: thousands separators, percent and duration
+    /// cards, a number sent as a number and cards we don't map. It is not a
+    /// recorded response and uses the unconfirmed names of `DIV1_STATS`, so it
+    /// only covers reading the cards by name.
+    const DIV1_STATSCARD: &str = include_str!("../../tests/fixtures/div1_statscard.json");
+
+    fn profile() -> ProfileDTO {
+        ProfileDTO {
+            id: "00000000-0000-4000-8000-000000000001".to_string(),
+            platform: Platform::Uplay,
+            name: Some("Agent".to_string()),
+        }
+    }
+
+    fn card(stat_name: &str, value: &str) -> StatCard {
+        StatCard { stat_name: stat_name.to_string(), display_name: None, value: json!(value), format: None, unit: None }
+    }
+
+    #[test]
+    fn div1_stats_from_statscard() {
+        let cards = parse_statscards(&from_str(DIV1_STATSCARD).unwrap()).unwrap();
+        for (name, _) in DIV1_STATS {
+            assert!(cards.iter().any(|c| c.stat_name == *name), "fixture has no {} card", name);
+        }
+        let s = div1_stats(profile(), cards);
+
+        assert_eq!(s.level, 30);
+        assert_eq!(s.gear_score, 256);
+        assert_eq!(s.dz_rank, 99);
+        assert_eq!(s.ug_rank, 40);
+        assert_eq!(s.playtime_secs, 1_234_567);
+        assert_eq!(s.playtime, 342);
+        assert_eq!(s.main_story, "100 %");
+        assert_eq!(s.main_story_percent, 100.0);
+        assert_eq!(s.total_kills, 23_456);
+        assert_eq!(s.npc_kills, 21_987);
+        assert_eq!(s.headshot_kills, 7_890);
+        assert_eq!(s.skill_kills, 1_203);
+        assert_eq!(s.rogue_kills, 512);
+        assert_eq!(s.items_extracted, 348);
+        assert_eq!(
+            s.extra.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("longestSurvivalTime".to_string(), json!(5400)),
+                ("missionsCompleted".to_string(), json!(214)),
+                ("namedEnemiesKilled".to_string(), json!(1046)),
+            ]
+        );
+    }
+
+    #[test]
+    fn div1_stats_by_position_without_known_names() {
+        let values = ["30", "99", "40", "36000", "67 %", "21", "17", "55", "1,200", "300", "1,100", "256"];
+        let cards = values.iter().enumerate().map(|(i, v)| card(&format!("stat{}", i), v)).collect();
+        let s = div1_stats(profile(), cards);
+
+        assert_eq!((s.level, s.dz_rank, s.ug_rank), (30, 99, 40));
+        assert_eq!((s.playtime_secs, s.playtime), (36000, 10));
+        assert_eq!((s.main_story.as_str(), s.main_story_percent), ("67 %", 67.0));
+        assert_eq!((s.rogue_kills, s.items_extracted, s.skill_kills, s.total_kills), (21, 17, 55, 1200));
+        assert_eq!(s.gear_score, 256);
+        // never read by position
+        assert_eq!((s.headshot_kills, s.npc_kills), (0, 0));
+        assert_eq!(s.extra.keys().collect::<Vec<_>>(), vec!["stat10", "stat9"]);
+    }
+
+    #[test]
+    fn div1_stats_by_position_only_for_missing_names() {
+        let mut cards = vec![
+            card("playerLevel", "30"),
+            card("rankDZ", "99"),
+            card("dzRank", "98"),
+            card("timePlayed", "36000"),
+        ];
+        cards.extend((4..12).map(|i| card(&format!("stat{}", i), "1")));
+        cards.push(card("gearScore", "256"));
+        let s = div1_stats(profile(), cards);
+
+        assert_eq!((s.level, s.dz_rank, s.playtime_secs, s.gear_score), (30, 99, 36000, 256));
+        // rankUG is read at its position, gearScore by name leaves card 11 alone
+        assert_eq!(s.ug_rank, 98);
+        assert_eq!(s.extra.keys().collect::<Vec<_>>(), vec!["stat10", "stat11", "stat9"]);
+    }
+
+    #[test]
+    fn profile_ids_are_uuids() {
+        assert!(is_valid_profile_id("5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5f60"));
+        assert!(is_valid_profile_id("5F3C1C2E-0B5A-4A4E-9A43-1D2C3B4A5F60"));
+        for id in [
+            "",
+            "MockAgent",
+            "5f3c1c2e0b5a4a4e9a431d2c3b4a5f60",
+            "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5f6",
+            "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5f600",
+            "5f3c1c2e-0b5a-4a4e-9a43_1d2c3b4a5f60",
+            "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5g60",
+            "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5é0",
+        ] {
+            assert!(!is_valid_profile_id(id), "{} taken for a profile id", id);
+        }
+    }
+
+    #[test]
+    fn statscards_must_be_an_array() {
+        assert!(matches!(parse_statscards(&json!({ "Statscards": null })), Err(UbiError::ParseError(_))));
+    }
+}
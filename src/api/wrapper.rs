@@ -1,33 +1,414 @@
+use futures::StreamExt;
 use serde_json::json;
 use sqlx::{Pool, Sqlite};
+use std::future::Future;
 
-use crate::api::ubi;
+use crate::api::{compare, error::{UbiError, UbiResult}, history::{self, HistoryQuery}, leaderboard::{self, LeaderboardQuery}, player, progress, session::UbiSessionPool, tracked, ubi};
+use crate::config::AppConfig;
 use crate::util::message;
+use crate::model::batch::{BatchItem, BatchRequest};
+use crate::model::div::{Div2Source, Division};
 use crate::model::response::{ApiStatus, ResponseWithStatus};
+use crate::model::snapshot::ProgressDTO;
+use crate::model::tracked::TrackRequest;
+use crate::model::ubi::{Platform, StoredPlayerDTO};
 
+/// When Ubisoft can't be reached, answer with the profiles and names we have
+/// `stored` for the player, flagged with `upstream_available: false`.
+async fn degraded_or_error(
+    err: UbiError,
+    stored: impl Future<Output = UbiResult<Vec<StoredPlayerDTO>>>,
+) -> ResponseWithStatus {
+    if !matches!(err, UbiError::UpstreamUnavailable(_) | UbiError::AuthFailed(_)) {
+        return ResponseWithStatus::from(&err);
+    }
+    match stored.await {
+        Ok(players) if !players.is_empty() => ResponseWithStatus::new(
+            ApiStatus::Ok,
+            message::MESSAGE_UPSTREAM_UNAVAILABLE.to_string(),
+            Some(json!({
+                "upstream_available": false,
+                "reason": err.error_code(),
+                "players": players,
+            })),
+        ),
+        _ => ResponseWithStatus::from(&err),
+    }
+}
 
-pub async fn get_div1_player_stats(pool: &Pool<Sqlite>, name: &str) -> ResponseWithStatus {
-    let stats = ubi::get_div1_player_stats(pool, name).await;
+pub async fn get_div1_player_stats(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, platform: &str, name: &str, pick: Option<&str>) -> ResponseWithStatus {
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let stats = ubi::get_div1_player_stats(pool, sessions, config, platform, name, pick).await;
     match stats {
         Ok(stats) => {
             ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
         },
         Err(err) => {
             println!("Error: {}\nwhen querying player {}", err, name);
-            ResponseWithStatus::new(ApiStatus::NotFound, err.to_string(), None)
+            degraded_or_error(err, ubi::get_stored_players_by_name(pool, platform, name)).await
         }
     }
 }
 
-pub async fn get_div2_player_stats(pool: &Pool<Sqlite>, name: &str) -> ResponseWithStatus {
-    let stats = ubi::get_div2_player_stats(pool, name).await;
+fn parse_div2_source(source: Option<&str>) -> UbiResult<Option<Div2Source>> {
+    match source {
+        Some(s) => Div2Source::parse(s)
+            .map(Some)
+            .ok_or_else(|| UbiError::InvalidRequest(format!("unknown source {}, expected ubi or tracker", s))),
+        None => Ok(None),
+    }
+}
+
+pub async fn get_div2_player_stats(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, platform: &str, name: &str, pick: Option<&str>, source: Option<&str>) -> ResponseWithStatus {
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let stats = ubi::get_div2_player_stats(pool, sessions, config, platform, name, pick, source).await;
     match stats {
         Ok(stats) => {
             ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
         },
         Err(err) => {
             println!("Error: {}\nwhen querying player {}", err, name);
-            ResponseWithStatus::new(ApiStatus::NotFound, err.to_string(), None)
+            degraded_or_error(err, ubi::get_stored_players_by_name(pool, platform, name)).await
+        }
+    }
+}
+
+/// Stats of the profile `id`, which unlike a name never changes hands.
+pub async fn get_div1_player_stats_by_id(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, id: &str) -> ResponseWithStatus {
+    if !ubi::is_valid_profile_id(id) {
+        return ResponseWithStatus::from(&UbiError::InvalidProfileId(id.to_string()));
+    }
+    let id = id.to_lowercase();
+    let stats = ubi::get_div1_player_stats_by_id(pool, sessions, config, &id).await;
+    match stats {
+        Ok(stats) => {
+            ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
+        },
+        Err(err) => {
+            println!("Error: {}\nwhen querying player id {}", err, id);
+            degraded_or_error(err, ubi::get_stored_player_by_id(pool, &id)).await
+        }
+    }
+}
+
+pub async fn get_div2_player_stats_by_id(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, id: &str, source: Option<&str>) -> ResponseWithStatus {
+    if !ubi::is_valid_profile_id(id) {
+        return ResponseWithStatus::from(&UbiError::InvalidProfileId(id.to_string()));
+    }
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let id = id.to_lowercase();
+    let stats = ubi::get_div2_player_stats_by_id(pool, sessions, config, &id, source).await;
+    match stats {
+        Ok(stats) => {
+            ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
+        },
+        Err(err) => {
+            println!("Error: {}\nwhen querying player id {}", err, id);
+            degraded_or_error(err, ubi::get_stored_player_by_id(pool, &id)).await
+        }
+    }
+}
+
+/// Players the batch looks up, or why it can't be run.
+pub fn batch_size(config: &AppConfig, batch: &BatchRequest) -> UbiResult<usize> {
+    if batch.players.is_empty() || batch.players.len() > config.batch_max_players {
+        return Err(UbiError::InvalidRequest(format!(
+            "players must contain 1 to {} entries",
+            config.batch_max_players
+        )));
+    }
+    let platform = batch.platform.as_deref().unwrap_or("uplay");
+    if Platform::parse(platform).is_none() {
+        return Err(UbiError::InvalidPlatform(platform.to_string()));
+    }
+    Ok(batch.players.len())
+}
+
+/// Looks every entry of the batch up, at most `upstream_concurrency` at a time.
+/// Each entry gets the answer its single lookup route would have given, so one
+/// unknown name doesn't fail the others.
+pub async fn get_batch_player_stats(
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    batch: &BatchRequest,
+) -> ResponseWithStatus {
+    if let Err(e) = batch_size(config, batch) {
+        return ResponseWithStatus::from(&e);
+    }
+    let platform = batch.platform.as_deref().unwrap_or("uplay");
+
+    let items = futures::stream::iter(batch.players.clone())
+        .map(|query| async move {
+            let query = query.trim();
+            let r = match (division, ubi::is_valid_profile_id(query)) {
+                (Division::One, true) => get_div1_player_stats_by_id(pool, sessions, config, query).await,
+                (Division::One, false) => get_div1_player_stats(pool, sessions, config, platform, query, None).await,
+                (Division::Two, true) => get_div2_player_stats_by_id(pool, sessions, config, query, None).await,
+                (Division::Two, false) => get_div2_player_stats(pool, sessions, config, platform, query, None, None).await,
+            };
+            BatchItem {
+                query: query.to_string(),
+                status_code: r.status_code,
+                response: r.response,
+            }
+        })
+        .buffered(config.upstream_concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let succeeded = items.iter().filter(|i| i.status_code == ApiStatus::Ok.code()).count();
+    ResponseWithStatus::new(
+        ApiStatus::Ok,
+        message::MESSAGE_BATCH_RESULTS.to_string(),
+        Some(json!({
+            "total": items.len(),
+            "succeeded": succeeded,
+            "results": items,
+        })),
+    )
+}
+
+fn progress_response(progress: UbiResult<ProgressDTO>, key: &str) -> ResponseWithStatus {
+    match progress {
+        Ok(progress) => {
+            let message = if progress.complete { message::MESSAGE_PROGRESS } else { message::MESSAGE_PROGRESS_INCOMPLETE };
+            ResponseWithStatus::new(ApiStatus::Ok, message.to_string(), Some(json!(progress)))
+        }
+        Err(err) => {
+            println!("Error: {}\nwhen querying progress of {}", err, key);
+            ResponseWithStatus::from(&err)
         }
     }
-}
\ No newline at end of file
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn get_player_progress(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, division: Division, platform: &str, name: &str, pick: Option<&str>, since: Option<&str>, source: Option<&str>) -> ResponseWithStatus {
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    progress_response(progress::get_progress(pool, sessions, config, division, platform, name, pick, since, source).await, name)
+}
+
+pub async fn get_player_progress_by_id(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, division: Division, id: &str, since: Option<&str>, source: Option<&str>) -> ResponseWithStatus {
+    if !ubi::is_valid_profile_id(id) {
+        return ResponseWithStatus::from(&UbiError::InvalidProfileId(id.to_string()));
+    }
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let id = id.to_lowercase();
+    progress_response(progress::get_progress_by_id(pool, sessions, config, division, &id, since, source).await, &id)
+}
+
+pub async fn get_player_history(pool: &Pool<Sqlite>, division: Division, platform: &str, name: &str, pick: Option<&str>, query: &HistoryQuery<'_>, source: Option<&str>) -> ResponseWithStatus {
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let history = match history::get_stored_profile_by_name(pool, platform, name, pick).await {
+        Ok(profile) => history::get_history(pool, division, profile, query, source).await,
+        Err(e) => Err(e),
+    };
+    match history {
+        Ok(history) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_HISTORY.to_string(), Some(json!(history))),
+        Err(err) => {
+            println!("Error: {}\nwhen querying history of {}", err, name);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub async fn get_player_history_by_id(pool: &Pool<Sqlite>, division: Division, id: &str, query: &HistoryQuery<'_>, source: Option<&str>) -> ResponseWithStatus {
+    if !ubi::is_valid_profile_id(id) {
+        return ResponseWithStatus::from(&UbiError::InvalidProfileId(id.to_string()));
+    }
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let id = id.to_lowercase();
+    let history = match history::get_stored_profile_by_id(pool, &id).await {
+        Ok(profile) => history::get_history(pool, division, profile, query, source).await,
+        Err(e) => Err(e),
+    };
+    match history {
+        Ok(history) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_HISTORY.to_string(), Some(json!(history))),
+        Err(err) => {
+            println!("Error: {}\nwhen querying history of {}", err, id);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub async fn get_leaderboard(pool: &Pool<Sqlite>, division: Division, query: &LeaderboardQuery<'_>) -> ResponseWithStatus {
+    match leaderboard::get_leaderboard(pool, division, query).await {
+        Ok(board) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_LEADERBOARD.to_string(), Some(json!(board))),
+        Err(err) => {
+            println!("Error: {}\nwhen ranking {} players", err, division.as_str());
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+/// Players the comparison looks up, or why it can't be run.
+pub fn compare_size(names: Option<&str>, platform: Option<&str>, source: Option<&str>) -> UbiResult<usize> {
+    let names = compare::parse_names(names)?;
+    let platform = platform.unwrap_or("uplay");
+    if Platform::parse(platform).is_none() {
+        return Err(UbiError::InvalidPlatform(platform.to_string()));
+    }
+    parse_div2_source(source)?;
+    Ok(names.len())
+}
+
+/// Compares the players in `names`; entries that fail are reported inside the
+/// comparison, only a malformed request fails as a whole.
+pub async fn get_compare(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, division: Division, names: Option<&str>, platform: Option<&str>, source: Option<&str>) -> ResponseWithStatus {
+    let names = match compare::parse_names(names) {
+        Ok(n) => n,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let platform = platform.unwrap_or("uplay");
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let source = match parse_div2_source(source) {
+        Ok(s) => s,
+        Err(e) => return ResponseWithStatus::from(&e),
+    };
+    let comparison = compare::compare_players(pool, sessions, config, division, names, platform, source).await;
+    ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_COMPARE.to_string(), Some(json!(comparison)))
+}
+
+pub async fn list_tracked_players(pool: &Pool<Sqlite>) -> ResponseWithStatus {
+    match tracked::list_tracked_players(pool).await {
+        Ok(players) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_TRACKED_PLAYERS.to_string(), Some(json!(players))),
+        Err(err) => {
+            println!("Error: {}\nwhen listing tracked players", err);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub async fn track_player(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, request: &TrackRequest) -> ResponseWithStatus {
+    match tracked::track_player(pool, sessions, request).await {
+        Ok((player, added)) => ResponseWithStatus::new(
+            if added { ApiStatus::Created } else { ApiStatus::Ok },
+            message::MESSAGE_TRACKED.to_string(),
+            Some(json!(player)),
+        ),
+        Err(err) => {
+            println!("Error: {}\nwhen tracking player {}", err, request.player);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub async fn untrack_player(pool: &Pool<Sqlite>, game: &str, id: &str) -> ResponseWithStatus {
+    match tracked::untrack_player(pool, game, id).await {
+        Ok(()) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_UNTRACKED.to_string(), None),
+        Err(err) => {
+            println!("Error: {}\nwhen untracking player {}", err, id);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub fn list_games(config: &AppConfig) -> ResponseWithStatus {
+    ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_GAMES.to_string(), Some(json!(config.games)))
+}
+
+pub async fn get_game_player_stats(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, slug: &str, platform: &str, name: &str, pick: Option<&str>) -> ResponseWithStatus {
+    let game = match config.game(slug) {
+        Some(g) => g,
+        None => return ResponseWithStatus::from(&UbiError::UnknownGame(slug.to_string())),
+    };
+    let platform = match Platform::parse(platform) {
+        Some(p) => p,
+        None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(platform.to_string())),
+    };
+    let stats = ubi::get_game_player_stats(pool, sessions, config, game, platform, name, pick).await;
+    match stats {
+        Ok(stats) => {
+            ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
+        },
+        Err(err) => {
+            println!("Error: {}\nwhen querying {} player {}", err, slug, name);
+            degraded_or_error(err, ubi::get_stored_players_by_name(pool, platform, name)).await
+        }
+    }
+}
+
+pub async fn get_game_player_stats_by_id(pool: &Pool<Sqlite>, sessions: &UbiSessionPool, config: &AppConfig, slug: &str, id: &str) -> ResponseWithStatus {
+    let game = match config.game(slug) {
+        Some(g) => g,
+        None => return ResponseWithStatus::from(&UbiError::UnknownGame(slug.to_string())),
+    };
+    if !ubi::is_valid_profile_id(id) {
+        return ResponseWithStatus::from(&UbiError::InvalidProfileId(id.to_string()));
+    }
+    let id = id.to_lowercase();
+    let stats = ubi::get_game_player_stats_by_id(pool, sessions, config, game, &id).await;
+    match stats {
+        Ok(stats) => {
+            ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(stats)))
+        },
+        Err(err) => {
+            println!("Error: {}\nwhen querying {} player id {}", err, slug, id);
+            degraded_or_error(err, ubi::get_stored_player_by_id(pool, &id)).await
+        }
+    }
+}
+
+pub async fn search_players(pool: &Pool<Sqlite>, q: &str, platform: Option<&str>, limit: Option<i64>) -> ResponseWithStatus {
+    let platform = match platform {
+        Some(p) => match Platform::parse(p) {
+            Some(p) => Some(p),
+            None => return ResponseWithStatus::from(&UbiError::InvalidPlatform(p.to_string())),
+        },
+        None => None,
+    };
+    match player::search_players(pool, q, platform, limit).await {
+        Ok(players) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_SEARCH_RESULTS.to_string(), Some(json!(players))),
+        Err(err) => {
+            println!("Error: {}\nwhen searching players {}", err, q);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
+
+pub async fn get_name_timeline(pool: &Pool<Sqlite>, id: &str) -> ResponseWithStatus {
+    match player::get_name_timeline(pool, id).await {
+        Ok(timeline) => ResponseWithStatus::new(ApiStatus::Ok, message::MESSAGE_USER_EXISTS.to_string(), Some(json!(timeline))),
+        Err(err) => {
+            println!("Error: {}\nwhen querying names of {}", err, id);
+            ResponseWithStatus::from(&err)
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Canned Ubisoft services and tracker.gg responses for offline development.
+//!
+//! Run it with `cargo run --bin mock_upstream` and point the API at it:
+//! `UBI_SERVICES_URL=http://localhost:8001 TRACKER_URL=http://localhost:8001 TRACKER_USE_BROWSER=false`.
+//! Any username/password logs in; the known players are listed in `PLAYERS`,
+//! `MockAgent` exists on both uplay and psn as two different profiles.
+#[macro_use]
+extern crate rocket;
+
+use chrono::Utc;
+use rocket::serde::json::{json, Json, Value};
+
+struct MockPlayer {
+    id: &'static str,
+    name: &'static str,
+    platform: &'static str,
+    seed: u64,
+    has_div2: bool,
+}
+
+static PLAYERS: &[MockPlayer] = &[
+    MockPlayer { id: "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5f60", name: "MockAgent", platform: "uplay", seed: 7, has_div2: true },
+    MockPlayer { id: "8d6e2b1a-3c4d-4e5f-8a9b-0c1d2e3f4a5b", name: "DarkZoneRat", platform: "uplay", seed: 13, has_div2: true },
+    MockPlayer { id: "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d", name: "Div1Only", platform: "uplay", seed: 3, has_div2: false },
+    MockPlayer { id: "c4a7e9d2-6b1f-4d3e-9a8c-2f5b7d9e1a3c", name: "MockAgent", platform: "psn", seed: 11, has_div2: true },
+    MockPlayer { id: "e1f2a3b4-c5d6-4e7f-8a9b-1c2d3e4f5a6b", name: "XboxAgent", platform: "xbl", seed: 5, has_div2: true },
+];
+
+fn find_player(key: &str, platform: Option<&str>) -> Option<&'static MockPlayer> {
+    PLAYERS.iter().find(|p| {
+        (p.id.eq_ignore_ascii_case(key) || p.name.eq_ignore_ascii_case(key))
+            && platform.is_none_or(|platform| p.platform == platform)
+    })
+}
+
+fn error(http_code: u16, message: &str) -> Value {
+    json!({ "errorCode": 1000 + http_code, "httpCode": http_code, "message": message })
+}
+
+#[post("/v3/profiles/sessions")]
+fn create_session() -> Json<Value> {
+    Json(json!({
+        "ticket": format!("mock-ticket-{}", Utc::now().timestamp()),
+        "sessionId": "00000000-0000-4000-8000-000000000000",
+        "expiration": (Utc::now() + chrono::Duration::hours(3)).to_rfc3339(),
+        "rememberMeTicket": "mock-remember-me",
+    }))
+}
+
+// Ubisoft's query parameters are camelCase and Rocket matches them by name
+#[get("/v2/profiles?<platformType>&<nameOnPlatform>&<idOnPlatform>&<userId>&<profileId>")]
+#[allow(non_snake_case)]
+fn get_profiles(
+    platformType: Option<&str>,
+    nameOnPlatform: Option<&str>,
+    idOnPlatform: Option<&str>,
+    userId: Option<&str>,
+    profileId: Option<&str>,
+) -> Json<Value> {
+    // a profileId alone is enough, the other lookups are scoped to a platform
+    let profiles = match profileId {
+        Some(id) => find_player(id, None),
+        None => find_player(nameOnPlatform.or(idOnPlatform).or(userId).unwrap_or(""), Some(platformType.unwrap_or("uplay"))),
+    }
+        .map(|p| {
+            vec![json!({
+                "profileId": p.id,
+                "userId": p.id,
+                "idOnPlatform": p.id,
+                "nameOnPlatform": p.name,
+                "platformType": p.platform,
+            })]
+        })
+        .unwrap_or_default();
+    Json(json!({ "profiles": profiles }))
+}
+
+static DIV2_SPACE_ID: &str = "60859c37-949d-49e2-8fc8-6d8dc40f1a9e";
+
+#[get("/v1/profiles/<id>/statscard?<spaceId>")]
+#[allow(non_snake_case)]
+fn get_statscard(id: &str, spaceId: Option<&str>) -> Json<Value> {
+    let p = match find_player(id, None) {
+        Some(p) => p,
+        None => return Json(error(404, "Profile not found")),
+    };
+    if spaceId == Some(DIV2_SPACE_ID) {
+        return Json(div2_statscard(p));
+    }
+    let n = |base: u64| (base * p.seed).to_string();
+    let card = |stat_name: &str, display_name: &str, value: String, unit: &str| {
+        json!({
+            "statName": stat_name,
+            "displayName": display_name,
+            "value": value,
+            "format": "number",
+            "unit": unit,
+        })
+    };
+    Json(json!({
+        // not in the order the API consumes them, and with a card it doesn't know
+        "Statscards": [
+            card("missionsCompleted", "Missions Completed", n(6), ""),
+            card("playerLevel", "Level", "30".to_string(), ""),
+            card("rankDZ", "Dark Zone Rank", n(9), ""),
+            card("rankUG", "Underground Rank", n(4), ""),
+            card("timePlayed", "Time Played", n(36000), "s"),
+            card("mainStoryProgress", "Main Story", format!("{} %", 60 + p.seed), "%"),
+            card("roguesKilled", "Rogues Killed", n(21), ""),
+            card("itemsExtracted", "Items Extracted", n(17), ""),
+            card("killsSkill", "Skill Kills", n(55), ""),
+            card("killsTotal", "Total Kills", n(1200), ""),
+            card("killsHeadshot", "Headshot Kills", n(300), ""),
+            card("killsNpc", "NPC Kills", n(1100), ""),
+            card("gearScore", "Gear Score", (200 + p.seed).to_string(), ""),
+        ]
+    }))
+}
+
+fn div2_statscard(p: &MockPlayer) -> Value {
+    if !p.has_div2 {
+        return error(404, "Profile not found");
+    }
+    let n = |base: u64| (base * p.seed).to_string();
+    let card = |stat_name: &str, value: String| {
+        json!({ "statName": stat_name, "displayName": stat_name, "value": value, "format": "number", "unit": "" })
+    };
+    json!({
+        "Statscards": [
+            card("timePlayed", n(72000)),
+            card("playerLevel", "40".to_string()),
+            card("killsPvP", n(80)),
+            card("killsNpc", n(4000)),
+            card("headshots", n(2500)),
+            card("killsHeadshot", n(900)),
+            card("playersKilled", n(80)),
+            card("xPTotal", n(900000)),
+            card("eCreditBalance", n(150000)),
+            card("commendationScore", n(3000)),
+            card("gearScore", "500".to_string()),
+            card("rankDZ", n(10)),
+            card("timePlayedDarkZone", n(14400)),
+            card("roguesKilled", n(25)),
+            card("conflictRank", n(5)),
+            card("timePlayedConflict", n(7200)),
+            card("summitFloorsCleared", n(12)),
+        ]
+    })
+}
+
+#[get("/api/v2/division-2/standard/profile/<platform>/<name>")]
+fn get_tracker_profile(platform: &str, name: &str) -> Json<Value> {
+    let p = match find_player(name, Some(platform)).filter(|p| p.has_div2) {
+        Some(p) => p,
+        None => {
+            return Json(json!({
+                "errors": [{ "code": "CollectorResultStatus::NotFound", "message": "Player not found" }]
+            }))
+        }
+    };
+    let v = |base: u64| json!({ "value": base * p.seed });
+    Json(json!({
+        "data": {
+            "platformInfo": { "platformSlug": platform, "platformUserHandle": p.name },
+            "segments": [{
+                "type": "overview",
+                "stats": {
+                    "timePlayed": v(72000),
+                    "highestPlayerLevel": { "value": 40 },
+                    "killsPvP": v(80),
+                    "killsNpc": v(4000),
+                    "headshots": v(2500),
+                    "killsHeadshot": v(900),
+                    "killsWeaponShotgun": v(300),
+                    "killsWeaponSubMachinegun": v(700),
+                    "killsWeaponPistol": v(50),
+                    "killsWeaponRifle": v(1600),
+                    "playersKilled": v(80),
+                    "xPTotal": v(900000),
+                    "xPPve": v(800000),
+                    "xPPvp": v(60000),
+                    "xPClan": v(40000),
+                    "killsSpecializationSharpshooter": v(120),
+                    "killsSpecializationSurvivalist": v(90),
+                    "killsSpecializationDemolitionist": v(110),
+                    "eCreditBalance": v(150000),
+                    "commendationCount": v(40),
+                    "commendationScore": v(3000),
+                    "latestGearScore": { "value": 500 },
+                    "rankDZ": v(10),
+                    "timePlayedDarkZone": v(14400),
+                    "roguesKilled": v(25),
+                    "timePlayedRogue": v(3600),
+                    "timePlayedRogueLongest": v(600),
+                    "latestConflictRank": v(5),
+                    "timePlayedConflict": v(7200),
+                }
+            }]
+        }
+    }))
+}
+
+#[launch]
+fn rocket() -> _ {
+    let port = std::env::var("MOCK_PORT")
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(8001);
+    rocket::custom(rocket::Config::figment().merge(("port", port)))
+        .mount("/", routes![create_session, get_profiles, get_statscard, get_tracker_profile])
+}
@@ -0,0 +1,290 @@
+use rocket::figment::{providers::{Env, Format, Toml}, Figment};
+use rocket::serde::Deserialize;
+use anyhow::anyhow;
+use std::path::Path;
+
+use crate::api::compare::MAX_COMPARE_PLAYERS;
+use crate::model::game::{GameDefinition, GamesFile};
+
+/// Credentials of one Ubisoft service account.
+#[derive(Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct UbiAccount {
+    pub username: String,
+    pub password: Option<String>,
+    pub totp_secret: Option<String>,
+}
+
+/// Every tunable of the service, read through Rocket's figment: `Rocket.toml`
+/// (per profile), then `ROCKET_*` env vars on top. The env vars used before
+/// this existed (`DATABASE_URL`, `UBI_USERNAME`, `CHROME_PORT`, ...) are still
+/// honoured as a fallback.
+#[derive(Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AppConfig {
+    pub database_url: String,
+    #[serde(default)]
+    pub ubi_accounts: Vec<UbiAccount>,
+    #[serde(default = "default_ubi_services_url")]
+    pub ubi_services_url: String,
+    #[serde(default = "default_tracker_url")]
+    pub tracker_url: String,
+    /// tracker.gg only answers real browsers; a mock tracker can be fetched directly.
+    #[serde(default = "default_true")]
+    pub tracker_use_browser: bool,
+    #[serde(default = "default_chrome_port")]
+    pub chrome_port: u16,
+    #[serde(default = "default_refresh_margin_secs")]
+    pub ubi_refresh_margin_secs: u64,
+    #[serde(default = "default_cooldown_secs")]
+    pub ubi_account_cooldown_secs: u64,
+    #[serde(default = "default_login_attempts")]
+    pub ubi_login_attempts: u32,
+    /// Requests per minute and client on the player routes.
+    #[serde(default = "default_rate_limit_per_minute")]
+    pub rate_limit_per_minute: u32,
+    /// Statscard requests in flight at once for a single lookup.
+    #[serde(default = "default_upstream_concurrency")]
+    pub upstream_concurrency: usize,
+    #[serde(default = "default_div1_space_id")]
+    pub div1_space_id: String,
+    #[serde(default = "default_div2_space_id")]
+    pub div2_space_id: String,
+    /// Most players accepted in one batch request.
+    #[serde(default = "default_batch_max_players")]
+    pub batch_max_players: usize,
+    /// Players per minute and client looked up through any route, shared by
+    /// single lookups, batches and comparisons.
+    #[serde(default = "default_lookup_budget_per_minute")]
+    pub lookup_budget_per_minute: u32,
+    /// How old a snapshot may be to stand in for a fresh lookup in comparisons.
+    #[serde(default = "default_compare_cache_secs")]
+    pub compare_cache_secs: u64,
+    /// Seconds between background refreshes of the tracked players, 0 turns them off.
+    #[serde(default = "default_tracked_refresh_interval_secs")]
+    pub tracked_refresh_interval_secs: u64,
+    /// Bearer token of the `/api/admin/...` routes, which are off without one.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Games served by `/api/games/<slug>/...`, on top of those in `games_file`.
+    #[serde(default)]
+    pub games: Vec<GameDefinition>,
+    /// TOML or JSON (by extension) file with a `games` list; skipped when missing.
+    #[serde(default = "default_games_file")]
+    pub games_file: String,
+}
+
+fn default_ubi_services_url() -> String { "https://public-ubiservices.ubi.com".to_string() }
+fn default_tracker_url() -> String { "https://api.tracker.gg".to_string() }
+fn default_true() -> bool { true }
+fn default_chrome_port() -> u16 { 9515 }
+fn default_refresh_margin_secs() -> u64 { 600 }
+fn default_cooldown_secs() -> u64 { 300 }
+fn default_login_attempts() -> u32 { 5 }
+fn default_rate_limit_per_minute() -> u32 { 5 }
+fn default_upstream_concurrency() -> usize { 5 }
+fn default_div1_space_id() -> String { "6edd234a-abff-4e90-9aab-b9b9c6e49ff7".to_string() }
+fn default_div2_space_id() -> String { "60859c37-949d-49e2-8fc8-6d8dc40f1a9e".to_string() }
+fn default_batch_max_players() -> usize { 50 }
+fn default_lookup_budget_per_minute() -> u32 { 60 }
+fn default_compare_cache_secs() -> u64 { 600 }
+fn default_tracked_refresh_interval_secs() -> u64 { 3600 }
+fn default_games_file() -> String { "games.toml".to_string() }
+
+/// Plain env vars from before the figment config, mapped to their config keys.
+const LEGACY_ENV: &[(&str, &str)] = &[
+    ("DATABASE_URL", "database_url"),
+    ("UBI_SERVICES_URL", "ubi_services_url"),
+    ("TRACKER_URL", "tracker_url"),
+    ("TRACKER_USE_BROWSER", "tracker_use_browser"),
+    ("CHROME_PORT", "chrome_port"),
+    ("UBI_REFRESH_MARGIN_SECS", "ubi_refresh_margin_secs"),
+    ("UBI_ACCOUNT_COOLDOWN_SECS", "ubi_account_cooldown_secs"),
+];
+
+/// `UBI_USERNAME` / `UBI_PASSWORD` / `UBI_TOTP_SECRET` and the numbered
+/// `UBI_USERNAME_1`, `UBI_USERNAME_2`, ... variants, stopping at the first gap.
+fn legacy_accounts() -> Vec<UbiAccount> {
+    let read = |suffix: &str| {
+        std::env::var(format!("UBI_USERNAME{}", suffix)).ok().map(|username| UbiAccount {
+            username,
+            password: std::env::var(format!("UBI_PASSWORD{}", suffix)).ok(),
+            totp_secret: std::env::var(format!("UBI_TOTP_SECRET{}", suffix)).ok(),
+        })
+    };
+
+    let mut accounts = vec![];
+    accounts.extend(read(""));
+    let mut i = 1;
+    while let Some(account) = read(&format!("_{}", i)) {
+        accounts.push(account);
+        i += 1;
+    }
+    accounts
+}
+
+fn load_games_file(path: &str) -> anyhow::Result<Vec<GameDefinition>> {
+    if !Path::new(path).exists() {
+        println!("Games file {} not found, only games set in the config are served", path);
+        return Ok(vec![]);
+    }
+    let file: GamesFile = if path.ends_with(".json") {
+        serde_json::from_str(&std::fs::read_to_string(path)?)?
+    } else {
+        Figment::from(Toml::file(path)).extract()?
+    };
+    Ok(file.games)
+}
+
+impl AppConfig {
+    /// Rocket's own figment with the legacy env vars joined in at the lowest priority.
+    pub fn figment() -> Figment {
+        let legacy_key = |key: &str| LEGACY_ENV.iter().find(|(env, _)| key.eq_ignore_ascii_case(env));
+        let legacy = Env::raw()
+            .filter(move |key| legacy_key(key.as_str()).is_some())
+            .map(move |key| legacy_key(key.as_str()).map(|(_, config)| *config).unwrap_or("").into());
+        rocket::Config::figment().join(legacy)
+    }
+
+    pub fn load(figment: &Figment) -> anyhow::Result<AppConfig> {
+        let mut config: AppConfig = figment
+            .extract()
+            .map_err(|e| anyhow!("Invalid configuration: {}", e))?;
+        if config.ubi_accounts.is_empty() {
+            config.ubi_accounts = legacy_accounts();
+        }
+        config.ubi_services_url = config.ubi_services_url.trim_end_matches('/').to_string();
+        config.tracker_url = config.tracker_url.trim_end_matches('/').to_string();
+        let games = load_games_file(&config.games_file)
+            .map_err(|e| anyhow!("Invalid games file {}: {}", config.games_file, e))?;
+        config.games.extend(games);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.database_url.is_empty() {
+            return Err(anyhow!("database_url must be set"));
+        }
+        if self.ubi_accounts.is_empty() {
+            return Err(anyhow!("No Ubi account configured, set ubi_accounts or UBI_USERNAME"));
+        }
+        if let Some(a) = self.ubi_accounts.iter().find(|a| a.username.is_empty()) {
+            return Err(anyhow!("Ubi account with empty username (password set: {})", a.password.is_some()));
+        }
+        for (key, url) in [("ubi_services_url", &self.ubi_services_url), ("tracker_url", &self.tracker_url)] {
+            match reqwest::Url::parse(url) {
+                Ok(u) if u.scheme() == "http" || u.scheme() == "https" => {}
+                _ => return Err(anyhow!("{} must be an http(s) url, got {}", key, url)),
+            }
+        }
+        if self.ubi_login_attempts == 0 {
+            return Err(anyhow!("ubi_login_attempts must be at least 1"));
+        }
+        if self.rate_limit_per_minute == 0 {
+            return Err(anyhow!("rate_limit_per_minute must be at least 1"));
+        }
+        if self.upstream_concurrency == 0 {
+            return Err(anyhow!("upstream_concurrency must be at least 1"));
+        }
+        if self.batch_max_players == 0 {
+            return Err(anyhow!("batch_max_players must be at least 1"));
+        }
+        if (self.lookup_budget_per_minute as usize) < self.batch_max_players.max(MAX_COMPARE_PLAYERS) {
+            return Err(anyhow!(
+                "lookup_budget_per_minute must be at least batch_max_players and {}, the most players a comparison looks up",
+                MAX_COMPARE_PLAYERS
+            ));
+        }
+        if self.div1_space_id.is_empty() || self.div2_space_id.is_empty() {
+            return Err(anyhow!("div1_space_id and div2_space_id must be set"));
+        }
+        for (i, game) in self.games.iter().enumerate() {
+            if game.slug.is_empty() || !game.slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(anyhow!("Game slug {:?} must be lowercase letters, digits or '-'", game.slug));
+            }
+            if self.games[..i].iter().any(|g| g.slug == game.slug) {
+                return Err(anyhow!("Game slug {} is defined twice", game.slug));
+            }
+            if game.space_id.is_empty() {
+                return Err(anyhow!("Game {} has no space_id", game.slug));
+            }
+            if let Some(f) = game.stats.values().find(|f| f.field().is_empty() || f.divisor().is_nan() || f.divisor() <= 0.0) {
+                return Err(anyhow!("Game {} maps a stat to an empty field or a divide that isn't above 0 ({:?})", game.slug, f));
+            }
+            let mut fields = game.stats.values().map(|f| f.field()).collect::<Vec<_>>();
+            fields.sort_unstable();
+            if let Some(w) = fields.windows(2).find(|w| w[0] == w[1]) {
+                return Err(anyhow!("Game {} maps two stats to the field {}", game.slug, w[0]));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn game(&self, slug: &str) -> Option<&GameDefinition> {
+        self.games.iter().find(|g| g.slug == slug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        database_url = "sqlite://test.db"
+        ubi_accounts = [{ username = "agent@example.com", password = "secret" }]
+    "#;
+
+    fn config(toml: &str) -> AppConfig {
+        Figment::from(Toml::string(&format!("{}\n{}", BASE, toml))).extract().unwrap()
+    }
+
+    fn game(stats: &str) -> String {
+        format!("[[games]]\nslug = \"wildlands\"\nname = \"Wildlands\"\nspace_id = \"<spaceId>\"\n[games.stats]\n{}", stats)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        config("").validate().unwrap();
+        config(&game("timePlayed = { field = \"playtime\", divide = 3600 }\nkillsNpc = \"npc_kills\"")).validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        for toml in [
+            "ubi_services_url = \"ftp://example.com\"",
+            "tracker_url = \"not a url\"",
+            "ubi_login_attempts = 0",
+            "rate_limit_per_minute = 0",
+            "upstream_concurrency = 0",
+            "batch_max_players = 0",
+            "lookup_budget_per_minute = 9",
+            "batch_max_players = 100",
+            "div2_space_id = \"\"",
+        ] {
+            assert!(config(toml).validate().is_err(), "{} accepted", toml);
+        }
+        let mut c = config("");
+        c.ubi_accounts.clear();
+        assert!(c.validate().is_err());
+        c = config("");
+        c.ubi_accounts[0].username.clear();
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_games() {
+        for toml in [
+            game("killsNpc = \"\""),
+            game("timePlayed = { field = \"playtime\", divide = 0 }"),
+            game("timePlayed = { field = \"playtime\", divide = -3600 }"),
+            game("timePlayed = { field = \"playtime\", divide = nan }"),
+            game("killsNpc = \"kills\"\nkillsPvP = \"kills\""),
+            game("").replace("wildlands", "Wild Lands"),
+            game("").replace("<spaceId>", ""),
+            format!("{}\n{}", game(""), game("")),
+        ] {
+            assert!(config(&toml).validate().is_err(), "{} accepted", toml);
+        }
+    }
+}
@@ -1,2 +1,51 @@
 pub mod user;
-type DBResult<T> = anyhow::Result<T>;
\ No newline at end of file
+pub mod session;
+pub mod snapshot;
+pub mod tracked;
+type DBResult<T> = anyhow::Result<T>;
+
+/// The database holds live Ubisoft session tickets, so keep it readable by the
+/// service user only. Non-file urls (e.g. `sqlite::memory:`) are left alone.
+pub fn protect_database_file(url: &str) -> anyhow::Result<()> {
+    let path = url
+        .trim_start_matches("sqlite:")
+        .trim_start_matches("//")
+        .split('?')
+        .next()
+        .unwrap_or("");
+    if path.is_empty() || path.starts_with(':') {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for file in [path.to_string(), format!("{}-wal", path), format!("{}-shm", path)] {
+            if std::path::Path::new(&file).exists() {
+                std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A migrated database of its own in the temp dir, for tests that need one.
+#[cfg(test)]
+pub async fn test_pool() -> sqlx::Pool<sqlx::Sqlite> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "divtracker-test-{}-{}.db",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ));
+    for file in [path.clone(), path.with_extension("db-wal"), path.with_extension("db-shm")] {
+        let _ = std::fs::remove_file(file);
+    }
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .expect("Couldn't create the test database");
+    sqlx::migrate!().run(&pool).await.expect("Couldn't migrate the test database");
+    pool
+}
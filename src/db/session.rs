@@ -0,0 +1,44 @@
+use super::DBResult;
+use sqlx::{Pool, Sqlite};
+
+use crate::model::ubi::SessionDTO;
+
+pub async fn get_session(pool: &Pool<Sqlite>, account: &str) -> DBResult<Option<SessionDTO>> {
+    let mut connection = pool.acquire().await?;
+    let session = sqlx::query_as!(
+        SessionDTO,
+        r#"
+        SELECT ticket, session_id, expiration, remember_me_ticket FROM ubi_sessions WHERE account = $1;
+        "#,
+        account
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(session)
+}
+
+pub async fn store_session(pool: &Pool<Sqlite>, account: &str, session: &SessionDTO) -> DBResult<bool> {
+    let mut connection = pool.acquire().await?;
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO ubi_sessions (account, ticket, session_id, expiration, remember_me_ticket) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (account) DO UPDATE SET
+            ticket = excluded.ticket,
+            session_id = excluded.session_id,
+            expiration = excluded.expiration,
+            remember_me_ticket = excluded.remember_me_ticket,
+            updated_at = CURRENT_TIMESTAMP;
+        "#,
+        account,
+        session.ticket,
+        session.session_id,
+        session.expiration,
+        session.remember_me_ticket
+    )
+    .execute(&mut *connection)
+    .await?
+    .rows_affected();
+
+    Ok(r > 0)
+}
@@ -0,0 +1,401 @@
+use super::DBResult;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+
+use crate::model::snapshot::{LeaderboardEntryDTO, SnapshotDTO};
+
+/// Stores `stats` as a new snapshot, unless they equal the latest snapshot of the
+/// player for `game` and `source`, which then only gets its `last_fetched_at`
+/// moved. Either way the `stats_latest` rows of `source` are replaced by these
+/// stats. Returns whether a snapshot was added.
+pub async fn store_snapshot(pool: &Pool<Sqlite>, user_id: &str, game: &str, source: &str, stats: &Value) -> DBResult<bool> {
+    let body = stats.to_string();
+    let hash = format!("{:x}", Sha256::digest(body.as_bytes()));
+
+    let mut tx = pool.begin().await?;
+    // Write before reading anything: a transaction that has read can't wait for
+    // a concurrent lookup's write and fails with "database is locked" instead.
+    let confirmed = sqlx::query!(
+        r#"
+        UPDATE stats_snapshots SET last_fetched_at = CURRENT_TIMESTAMP
+        WHERE content_hash = $4 AND id = (
+            SELECT id FROM stats_snapshots
+            WHERE user_id = $1 AND game = $2 AND source = $3
+            ORDER BY fetched_at DESC, id DESC LIMIT 1
+        );
+        "#,
+        user_id,
+        game,
+        source,
+        hash
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if !confirmed {
+        sqlx::query!(
+            r#"
+            INSERT INTO stats_snapshots (user_id, game, source, stats, content_hash) VALUES ($1, $2, $3, $4, $5);
+            "#,
+            user_id,
+            game,
+            source,
+            body,
+            hash
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // The latest values of this source are exactly the ones just fetched.
+    sqlx::query!(
+        r#"
+        DELETE FROM stats_latest WHERE user_id = $1 AND game = $2 AND source = $3;
+        "#,
+        user_id,
+        game,
+        source
+    )
+    .execute(&mut *tx)
+    .await?;
+    for (field, value) in numeric_fields(stats) {
+        sqlx::query!(
+            r#"
+            INSERT INTO stats_latest (user_id, game, source, field, value) VALUES ($1, $2, $3, $4, $5);
+            "#,
+            user_id,
+            game,
+            source,
+            field,
+            value
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(!confirmed)
+}
+
+/// Numeric fields of a snapshot, unmapped statscards as `extra.<statName>`.
+fn numeric_fields(stats: &Value) -> Vec<(String, f64)> {
+    let mut fields = vec![];
+    if let Some(stats) = stats.as_object() {
+        for (key, value) in stats {
+            match value {
+                Value::Number(n) => fields.extend(n.as_f64().map(|v| (key.clone(), v))),
+                Value::Object(extra) if key == "extra" => fields.extend(
+                    extra
+                        .iter()
+                        .filter_map(|(name, v)| v.as_f64().map(|v| (format!("extra.{}", name), v))),
+                ),
+                _ => {}
+            }
+        }
+    }
+    fields
+}
+
+/// Most recently confirmed snapshot of the player for `game`, from `source` or any source.
+pub async fn get_latest_snapshot(pool: &Pool<Sqlite>, user_id: &str, game: &str, source: Option<&str>) -> DBResult<Option<SnapshotDTO>> {
+    let mut connection = pool.acquire().await?;
+    let snapshot = sqlx::query_as!(
+        SnapshotDTO,
+        r#"
+        SELECT
+            source,
+            stats,
+            strftime('%Y-%m-%dT%H:%M:%SZ', fetched_at) AS "fetched_at!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', last_fetched_at) AS "last_fetched_at!: String"
+        FROM stats_snapshots
+        WHERE user_id = $1 AND game = $2 AND ($3 IS NULL OR source = $3)
+        ORDER BY last_fetched_at DESC, id DESC LIMIT 1;
+        "#,
+        user_id,
+        game,
+        source
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(snapshot)
+}
+
+/// The snapshot in effect at `at` (`YYYY-MM-DD HH:MM:SS`, UTC): the latest one
+/// fetched at or before it. Without one, the oldest snapshot and false.
+pub async fn get_snapshot_at(pool: &Pool<Sqlite>, user_id: &str, game: &str, source: &str, at: &str) -> DBResult<Option<(SnapshotDTO, bool)>> {
+    let mut connection = pool.acquire().await?;
+    let snapshot = sqlx::query_as!(
+        SnapshotDTO,
+        r#"
+        SELECT
+            source,
+            stats,
+            strftime('%Y-%m-%dT%H:%M:%SZ', fetched_at) AS "fetched_at!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', last_fetched_at) AS "last_fetched_at!: String"
+        FROM stats_snapshots
+        WHERE user_id = $1 AND game = $2 AND source = $3 AND fetched_at <= $4
+        ORDER BY fetched_at DESC, id DESC LIMIT 1;
+        "#,
+        user_id,
+        game,
+        source,
+        at
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+    if let Some(snapshot) = snapshot {
+        return Ok(Some((snapshot, true)));
+    }
+
+    let oldest = sqlx::query_as!(
+        SnapshotDTO,
+        r#"
+        SELECT
+            source,
+            stats,
+            strftime('%Y-%m-%dT%H:%M:%SZ', fetched_at) AS "fetched_at!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', last_fetched_at) AS "last_fetched_at!: String"
+        FROM stats_snapshots
+        WHERE user_id = $1 AND game = $2 AND source = $3
+        ORDER BY fetched_at ASC, id ASC LIMIT 1;
+        "#,
+        user_id,
+        game,
+        source
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(oldest.map(|s| (s, false)))
+}
+
+/// Snapshots in effect at some point between `from` and `to` (`YYYY-MM-DD HH:MM:SS`, UTC), oldest first.
+pub async fn get_snapshots_between(pool: &Pool<Sqlite>, user_id: &str, game: &str, source: &str, from: &str, to: &str) -> DBResult<Vec<SnapshotDTO>> {
+    let mut connection = pool.acquire().await?;
+    let snapshots = sqlx::query_as!(
+        SnapshotDTO,
+        r#"
+        SELECT
+            source,
+            stats,
+            strftime('%Y-%m-%dT%H:%M:%SZ', fetched_at) AS "fetched_at!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', last_fetched_at) AS "last_fetched_at!: String"
+        FROM stats_snapshots
+        WHERE user_id = $1 AND game = $2 AND source = $3 AND last_fetched_at >= $4 AND fetched_at <= $5
+        ORDER BY fetched_at ASC, id ASC;
+        "#,
+        user_id,
+        game,
+        source,
+        from,
+        to
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(snapshots)
+}
+
+/// Who makes it onto a leaderboard, every filter is optional.
+pub struct LeaderboardFilter<'a> {
+    pub min_level: Option<f64>,
+    /// `YYYY-MM-DD HH:MM:SS` (UTC) the stat must have been fetched after.
+    pub seen_since: Option<&'a str>,
+    pub platform: Option<&'a str>,
+    /// Only values from this source; otherwise whichever source last had the stat.
+    pub source: Option<&'a str>,
+}
+
+pub async fn has_stat(pool: &Pool<Sqlite>, game: &str, field: &str, source: Option<&str>) -> DBResult<bool> {
+    let mut connection = pool.acquire().await?;
+    let found = sqlx::query!(
+        r#"
+        SELECT 1 AS "found!: i64" FROM stats_latest WHERE game = $1 AND field = $2 AND ($3 IS NULL OR source = $3) LIMIT 1;
+        "#,
+        game,
+        field,
+        source
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+/// Players ranked by the latest known value of `field`, highest first, and how
+/// many match the filter in total. A player with the stat from several sources
+/// is ranked by the one updated last.
+pub async fn get_leaderboard(
+    pool: &Pool<Sqlite>,
+    game: &str,
+    field: &str,
+    filter: &LeaderboardFilter<'_>,
+    limit: i64,
+    offset: i64,
+) -> DBResult<(Vec<LeaderboardEntryDTO>, i64)> {
+    let mut connection = pool.acquire().await?;
+    let entries = sqlx::query_as!(
+        LeaderboardEntryDTO,
+        r#"
+        SELECT
+            ranked.board_rank AS "rank!: i64",
+            ranked.user_id AS "id!: String",
+            COALESCE((SELECT n.platform FROM user_names n WHERE n.user_id = ranked.user_id ORDER BY n.last_seen DESC LIMIT 1), '') AS "platform!: String",
+            COALESCE((SELECT n.name FROM user_names n WHERE n.user_id = ranked.user_id ORDER BY n.last_seen DESC LIMIT 1), '') AS "name!: String",
+            ranked.value AS "value!: f64",
+            ranked.source AS "source!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', ranked.updated_at) AS "updated_at!: String"
+        FROM (
+            SELECT s.user_id, s.value, s.source, s.updated_at, RANK() OVER (ORDER BY s.value DESC) AS board_rank
+            FROM stats_latest s
+            LEFT JOIN stats_latest lv ON lv.user_id = s.user_id AND lv.game = s.game AND lv.source = s.source AND lv.field = 'level'
+            WHERE s.game = $1 AND s.field = $2
+                AND ($6 IS NULL OR s.source = $6)
+                AND NOT EXISTS (
+                    SELECT 1 FROM stats_latest o
+                    WHERE o.user_id = s.user_id AND o.game = s.game AND o.field = s.field AND ($6 IS NULL OR o.source = $6)
+                        AND (o.updated_at > s.updated_at OR (o.updated_at = s.updated_at AND o.source > s.source))
+                )
+                AND ($3 IS NULL OR lv.value >= $3)
+                AND ($4 IS NULL OR s.updated_at >= $4)
+                AND ($5 IS NULL OR EXISTS (SELECT 1 FROM user_names n WHERE n.user_id = s.user_id AND n.platform = $5))
+        ) ranked
+        ORDER BY ranked.board_rank, ranked.updated_at DESC
+        LIMIT $7 OFFSET $8;
+        "#,
+        game,
+        field,
+        filter.min_level,
+        filter.seen_since,
+        filter.platform,
+        filter.source,
+        limit,
+        offset
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let total = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "total!: i64"
+        FROM stats_latest s
+        LEFT JOIN stats_latest lv ON lv.user_id = s.user_id AND lv.game = s.game AND lv.source = s.source AND lv.field = 'level'
+        WHERE s.game = $1 AND s.field = $2
+            AND ($6 IS NULL OR s.source = $6)
+            AND NOT EXISTS (
+                SELECT 1 FROM stats_latest o
+                WHERE o.user_id = s.user_id AND o.game = s.game AND o.field = s.field AND ($6 IS NULL OR o.source = $6)
+                    AND (o.updated_at > s.updated_at OR (o.updated_at = s.updated_at AND o.source > s.source))
+            )
+            AND ($3 IS NULL OR lv.value >= $3)
+            AND ($4 IS NULL OR s.updated_at >= $4)
+            AND ($5 IS NULL OR EXISTS (SELECT 1 FROM user_names n WHERE n.user_id = s.user_id AND n.platform = $5));
+        "#,
+        game,
+        field,
+        filter.min_level,
+        filter.seen_since,
+        filter.platform,
+        filter.source
+    )
+    .fetch_one(&mut *connection)
+    .await?
+    .total;
+
+    Ok((entries, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+    use crate::db::user::create_user;
+    use serde_json::json;
+
+    const USER: &str = "5f3c1c2e-0b5a-4a4e-9a43-1d2c3b4a5f60";
+
+    async fn snapshot_count(pool: &Pool<Sqlite>) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM stats_snapshots").fetch_one(pool).await.unwrap()
+    }
+
+    #[test]
+    fn numeric_fields_flatten_extra_and_skip_the_rest() {
+        let stats = json!({
+            "level": 30,
+            "main_story": "67 %",
+            "main_story_percent": 67.5,
+            "smg_kills": null,
+            "all_names": ["MockAgent"],
+            "derived": {"kills_per_hour": 120.0},
+            "extra": {"missionsCompleted": 42, "title": "Agent"},
+        });
+        assert_eq!(
+            numeric_fields(&stats),
+            vec![
+                ("level".to_string(), 30.0),
+                ("main_story_percent".to_string(), 67.5),
+                ("extra.missionsCompleted".to_string(), 42.0),
+            ]
+        );
+        assert_eq!(numeric_fields(&json!([1, 2])), vec![]);
+    }
+
+    #[rocket::async_test]
+    async fn identical_stats_only_confirm_the_latest_snapshot() {
+        let pool = test_pool().await;
+        create_user(&pool, USER).await.unwrap();
+
+        assert!(store_snapshot(&pool, USER, "div2", "tracker", &json!({"level": 30})).await.unwrap());
+        assert!(!store_snapshot(&pool, USER, "div2", "tracker", &json!({"level": 30})).await.unwrap());
+        assert!(store_snapshot(&pool, USER, "div2", "tracker", &json!({"level": 31})).await.unwrap());
+        assert!(store_snapshot(&pool, USER, "div2", "tracker", &json!({"level": 30})).await.unwrap());
+        assert_eq!(snapshot_count(&pool).await, 3);
+    }
+
+    #[rocket::async_test]
+    async fn concurrent_lookups_of_a_player_all_store() {
+        let pool = test_pool().await;
+        create_user(&pool, USER).await.unwrap();
+
+        let stores = (0..16).map(|i| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let stats = json!({"level": 30, "pvp_kills": i % 2});
+                store_snapshot(&pool, USER, "div2", "tracker", &stats).await
+            })
+        });
+        for stored in futures::future::join_all(stores).await {
+            stored.unwrap().expect("a concurrent store_snapshot failed");
+        }
+        let count = snapshot_count(&pool).await;
+        assert!((2..=16).contains(&count), "{} snapshots", count);
+    }
+
+    async fn board(pool: &Pool<Sqlite>, field: &str, source: Option<&str>) -> Vec<(f64, String)> {
+        let filter = LeaderboardFilter { min_level: None, seen_since: None, platform: None, source };
+        let (entries, total) = get_leaderboard(pool, "div2", field, &filter, 10, 0).await.unwrap();
+        assert_eq!(total, entries.len() as i64);
+        entries.into_iter().map(|e| (e.value, e.source)).collect()
+    }
+
+    #[rocket::async_test]
+    async fn statscard_lookup_keeps_the_tracker_values_on_the_leaderboard() {
+        let pool = test_pool().await;
+        create_user(&pool, USER).await.unwrap();
+
+        store_snapshot(&pool, USER, "div2", "tracker", &json!({"level": 40, "pvp_kills": 560, "smg_kills": 4900}))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE stats_latest SET updated_at = datetime('now', '-1 hour')").execute(&pool).await.unwrap();
+        store_snapshot(&pool, USER, "div2", "ubi", &json!({"level": 40, "pvp_kills": 600})).await.unwrap();
+
+        assert_eq!(board(&pool, "smg_kills", None).await, vec![(4900.0, "tracker".to_string())]);
+        assert_eq!(board(&pool, "smg_kills", Some("ubi")).await, vec![]);
+        assert_eq!(board(&pool, "pvp_kills", None).await, vec![(600.0, "ubi".to_string())]);
+        assert_eq!(board(&pool, "pvp_kills", Some("tracker")).await, vec![(560.0, "tracker".to_string())]);
+    }
+}
@@ -0,0 +1,98 @@
+use super::DBResult;
+use sqlx::{Pool, Sqlite};
+
+use crate::model::tracked::TrackedPlayerDTO;
+
+/// Starts tracking the player for `game`; false when they already are.
+pub async fn add_tracked_player(pool: &Pool<Sqlite>, user_id: &str, game: &str) -> DBResult<bool> {
+    let mut connection = pool.acquire().await?;
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO tracked_players (user_id, game) VALUES ($1, $2)
+        ON CONFLICT (user_id, game) DO NOTHING;
+        "#,
+        user_id,
+        game
+    )
+    .execute(&mut *connection)
+    .await?
+    .rows_affected();
+
+    Ok(r > 0)
+}
+
+/// Stops tracking the player for `game`; false when they weren't.
+pub async fn remove_tracked_player(pool: &Pool<Sqlite>, user_id: &str, game: &str) -> DBResult<bool> {
+    let mut connection = pool.acquire().await?;
+    let r = sqlx::query!(
+        r#"
+        DELETE FROM tracked_players WHERE user_id = $1 AND game = $2;
+        "#,
+        user_id,
+        game
+    )
+    .execute(&mut *connection)
+    .await?
+    .rows_affected();
+
+    Ok(r > 0)
+}
+
+/// Every tracked player with their most recent name, least recently refreshed first.
+pub async fn get_tracked_players(pool: &Pool<Sqlite>) -> DBResult<Vec<TrackedPlayerDTO>> {
+    let mut connection = pool.acquire().await?;
+    let players = sqlx::query_as!(
+        TrackedPlayerDTO,
+        r#"
+        SELECT
+            t.user_id AS "id!: String",
+            t.game AS "game!: String",
+            n.name AS "name?: String",
+            n.platform AS "platform?: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', t.added_at) AS "added_at!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', t.last_refresh_at) AS "last_refresh_at?: String",
+            t.last_status_code,
+            t.last_error_code,
+            t.last_message
+        FROM tracked_players t
+        LEFT JOIN user_names n ON n.user_id = t.user_id AND n.last_seen = (
+            SELECT MAX(last_seen) FROM user_names WHERE user_id = t.user_id
+        )
+        GROUP BY t.user_id, t.game
+        ORDER BY t.last_refresh_at IS NOT NULL, t.last_refresh_at, t.added_at;
+        "#
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(players)
+}
+
+/// Keeps the outcome of a background refresh: the status code the lookup
+/// route would have answered with, plus the error code and message on failure.
+pub async fn record_refresh(
+    pool: &Pool<Sqlite>,
+    user_id: &str,
+    game: &str,
+    status_code: u16,
+    error_code: Option<&str>,
+    message: Option<&str>,
+) -> DBResult<()> {
+    let mut connection = pool.acquire().await?;
+    sqlx::query!(
+        r#"
+        UPDATE tracked_players
+        SET last_refresh_at = CURRENT_TIMESTAMP, last_status_code = $3, last_error_code = $4, last_message = $5
+        WHERE user_id = $1 AND game = $2;
+        "#,
+        user_id,
+        game,
+        status_code,
+        error_code,
+        message
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
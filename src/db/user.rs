@@ -2,11 +2,13 @@ use super::DBResult;
 use rocket::futures::stream::TryStreamExt;
 use sqlx::{Pool, Sqlite};
 
+use crate::model::ubi::{NameHistoryDTO, NameHolderDTO, PlayerMatchDTO};
+
 pub async fn get_user_names_by_id(pool: &Pool<Sqlite>, id: &str) -> DBResult<Vec<String>> {
     let mut connection = pool.acquire().await?;
     let names = sqlx::query!(
         r#"
-        SELECT name FROM user_names WHERE user_id = $1 ORDER BY ts DESC;
+        SELECT name FROM user_names WHERE user_id = $1 ORDER BY last_seen DESC;
         "#,
         id
     )
@@ -17,12 +19,48 @@ pub async fn get_user_names_by_id(pool: &Pool<Sqlite>, id: &str) -> DBResult<Vec
     Ok(names)
 }
 
-pub async fn get_user_id_by_name(pool: &Pool<Sqlite>, name: &str) -> DBResult<Vec<String>> {
+pub async fn get_user_name_history_by_id(pool: &Pool<Sqlite>, id: &str) -> DBResult<Vec<NameHistoryDTO>> {
+    let mut connection = pool.acquire().await?;
+    let names = sqlx::query_as!(
+        NameHistoryDTO,
+        r#"
+        SELECT
+            name,
+            strftime('%Y-%m-%dT%H:%M:%SZ', first_seen) AS "first_seen!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', last_seen) AS "last_seen!: String",
+            sightings
+        FROM user_names WHERE user_id = $1 ORDER BY last_seen DESC;
+        "#,
+        id
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(names)
+}
+
+pub async fn get_user_platform_by_id(pool: &Pool<Sqlite>, id: &str) -> DBResult<Option<String>> {
+    let mut connection = pool.acquire().await?;
+    let platform = sqlx::query!(
+        r#"
+        SELECT platform FROM user_names WHERE user_id = $1 LIMIT 1;
+        "#,
+        id
+    )
+    .fetch_optional(&mut *connection)
+    .await?
+    .map(|r| r.platform);
+
+    Ok(platform)
+}
+
+pub async fn get_user_id_by_name(pool: &Pool<Sqlite>, platform: &str, name: &str) -> DBResult<Vec<String>> {
     let mut connection = pool.acquire().await?;
     let id = sqlx::query!(
         r#"
-        SELECT user_id FROM user_names WHERE LOWER(name) = LOWER($1) ORDER BY ts DESC;
+        SELECT user_id FROM user_names WHERE platform = $1 AND LOWER(name) = LOWER($2) ORDER BY last_seen DESC;
         "#,
+        platform,
         name
     )
     .fetch(&mut *connection)
@@ -33,11 +71,37 @@ pub async fn get_user_id_by_name(pool: &Pool<Sqlite>, name: &str) -> DBResult<Ve
     Ok(id)
 }
 
+/// Everyone we have seen use `name` on `platform`, latest holder first.
+pub async fn get_name_holders(pool: &Pool<Sqlite>, platform: &str, name: &str) -> DBResult<Vec<NameHolderDTO>> {
+    let mut connection = pool.acquire().await?;
+    let holders = sqlx::query_as!(
+        NameHolderDTO,
+        r#"
+        SELECT
+            n.user_id AS "id!",
+            (SELECT c.name FROM user_names c WHERE c.user_id = n.user_id ORDER BY c.last_seen DESC LIMIT 1) AS "current_name!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', n.first_seen) AS "first_seen!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', n.last_seen) AS "last_seen!: String"
+        FROM user_names n
+        WHERE n.platform = $1 AND LOWER(n.name) = LOWER($2)
+        ORDER BY n.last_seen DESC;
+        "#,
+        platform,
+        name
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(holders)
+}
+
+/// Creates the user or, when it already exists, counts one more lookup of it.
 pub async fn create_user(pool: &Pool<Sqlite>, id: &str) -> DBResult<bool> {
     let mut connection = pool.acquire().await?;
     let r = sqlx::query!(
         r#"
-        INSERT INTO user_ids (id) VALUES ($1) ON CONFLICT DO NOTHING;
+        INSERT INTO user_ids (id, lookups, last_seen) VALUES ($1, 1, CURRENT_TIMESTAMP)
+        ON CONFLICT (id) DO UPDATE SET lookups = lookups + 1, last_seen = CURRENT_TIMESTAMP;
         "#,
         id
     )
@@ -48,18 +112,64 @@ pub async fn create_user(pool: &Pool<Sqlite>, id: &str) -> DBResult<bool> {
     Ok(r > 0)
 }
 
-pub async fn store_user_name(pool: &Pool<Sqlite>, id: &str, name: &str) -> DBResult<bool> {
+/// Records a sighting of `name`, moving its `last_seen` forward when it is
+/// already known so a player going back to an old name sorts correctly.
+pub async fn store_user_name(pool: &Pool<Sqlite>, id: &str, platform: &str, name: &str) -> DBResult<bool> {
     let mut connection = pool.acquire().await?;
     let r = sqlx::query!(
         r#"
-        INSERT INTO user_names (user_id, name) VALUES ($1, $2) ON CONFLICT DO NOTHING;
+        INSERT INTO user_names (user_id, name, platform) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, name) DO UPDATE SET last_seen = CURRENT_TIMESTAMP, sightings = sightings + 1;
         "#,
         id,
-        name
+        name,
+        platform
     )
     .execute(&mut *connection)
     .await?
     .rows_affected();
 
     Ok(r > 0)
-}
\ No newline at end of file
+}
+
+/// Known players with a name starting with `prefix` (case-insensitive), one row
+/// per profile. Frequently and recently looked up profiles come first: lookups
+/// are divided by one plus the days since the last lookup. Ties, like profiles
+/// never looked up, go to the most recently seen, then by name.
+pub async fn search_user_names(
+    pool: &Pool<Sqlite>,
+    prefix: &str,
+    platform: Option<&str>,
+    limit: i64,
+) -> DBResult<Vec<PlayerMatchDTO>> {
+    let mut connection = pool.acquire().await?;
+    let pattern = format!(
+        "{}%",
+        prefix.to_lowercase().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    );
+    let matches = sqlx::query_as!(
+        PlayerMatchDTO,
+        r#"
+        SELECT
+            n.user_id AS "id!",
+            n.platform AS "platform!",
+            MIN(n.name) AS "matched_name!: String",
+            (SELECT c.name FROM user_names c WHERE c.user_id = n.user_id ORDER BY c.last_seen DESC LIMIT 1) AS "current_name!: String",
+            strftime('%Y-%m-%dT%H:%M:%SZ', COALESCE(u.last_seen, MAX(n.last_seen))) AS "last_seen: String",
+            u.lookups AS "lookups!"
+        FROM user_names n JOIN user_ids u ON u.id = n.user_id
+        WHERE LOWER(n.name) LIKE $1 ESCAPE '\' AND ($2 IS NULL OR n.platform = $2)
+        GROUP BY n.user_id
+        ORDER BY u.lookups * 1.0 / (1 + julianday('now') - julianday(COALESCE(u.last_seen, MAX(n.last_seen)))) DESC,
+            COALESCE(u.last_seen, MAX(n.last_seen)) DESC, MIN(n.name), n.user_id
+        LIMIT $3;
+        "#,
+        pattern,
+        platform,
+        limit
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(matches)
+}
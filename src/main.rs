@@ -3,6 +3,7 @@ extern crate rocket;
 extern crate lazy_static;
 
 mod api;
+mod config;
 mod model;
 mod util;
 mod middleware;
@@ -11,69 +12,483 @@ mod db;
 use rocket::State;
 use rocket::http::Status;
 use rocket::response::status;
-use rocket::serde::json::Json;
-use rocket_governor::RocketGovernor;
+use rocket::serde::json::{json, Json};
 
-use model::response::Response;
-use middleware::{governor::RateLimitGuard, cors::Cors, catcher::{exceed_rate_limit, not_found, internal_server_error}};
-use api::wrapper::{get_div1_player_stats, get_div2_player_stats};
-use api::ubi::login_ubi;
+use model::response::{ApiStatus, Response, ResponseWithStatus};
+use middleware::{admin::AdminGuard, governor::{LookupGuard, WeightedRateLimiter}, cors::Cors, catcher::{bad_request, exceed_rate_limit, forbidden, not_found, internal_server_error, unauthorized, unprocessable_entity}};
+use model::batch::BatchRequest;
+use model::tracked::TrackRequest;
+use model::div::Division;
+use api::wrapper::{batch_size, compare_size, get_batch_player_stats, get_compare, list_tracked_players, track_player, untrack_player, get_div1_player_stats, get_div1_player_stats_by_id, get_div2_player_stats, get_div2_player_stats_by_id, get_game_player_stats, get_leaderboard, get_game_player_stats_by_id, get_name_timeline, get_player_history, get_player_history_by_id, get_player_progress, get_player_progress_by_id, list_games, search_players};
+use api::history::HistoryQuery;
+use api::leaderboard::LeaderboardQuery;
+use api::session::UbiSessionPool;
+use config::AppConfig;
 
 use sqlx::{Pool, Sqlite, SqlitePool};
 use anyhow::Result;
+use std::net::IpAddr;
+use std::time::Duration;
 
 #[get("/")]
 async fn index() -> &'static str {
     r#"
     - 使用: 当前网址后加上以下路径
         /api/div1/<name> 获取全境1数据（育碧官方api）
-        /api/div2/<name> 获取全境2数据（api.tracker.gg）
+        /api/div2/<name> 获取全境2数据（默认 api.tracker.gg，失败时改用育碧 statscard；?source=ubi|tracker 指定来源）
+        /api/div1/<platform>/<name>, /api/div2/<platform>/<name> 主机玩家，platform 为 uplay / psn / xbl
+        /api/div1/id/<profile_id>, /api/div2/id/<profile_id> 按育碧 profileId 查询，改名后依然有效
+        /api/div1/<name>/progress?since=7d, /api/div2/<name>/progress?since=7d 从 since（7d、12h、2w 或日期）到现在的数据增量，同样支持 <platform>/<name> 和 id/<profile_id>
+        /api/div1/<name>/history?field=gear_score&from=365d&to=&bucket=day, /api/div2/<name>/history?... 某个字段的历史数据（按 hour/day/week/month 分桶），同样支持 <platform>/<name> 和 id/<profile_id>
+        /api/div1/leaderboard?stat=dz_rank&limit=50&offset=0, /api/div2/leaderboard?... 本服务查询过的玩家按某项数据排名，可用 min_level、max_age=30d、platform、source（全境2）过滤
+        /api/div1/compare?names=a,b,c, /api/div2/compare?names=a,b,c 2 到 10 名玩家（名字或 profileId）逐项对比，可加 platform、source（全境2）
+        名字对应多个玩家时返回 300 和候选列表，加 ?pick=<profile_id> 选择其中一个
+        /api/players/search?q=<前缀>&limit=10&platform=uplay 按名字前缀搜索已知玩家（不访问育碧）
+        /api/players/<profile_id>/names 玩家用过的所有名字及首次/最近出现时间
+        POST /api/div1/batch, /api/div2/batch 批量查询，body 为 {"players": [名字或 profileId], "platform": "uplay"}
+        /api/games 已配置的游戏；/api/games/<slug>/<name>、/api/games/<slug>/<platform>/<name>、/api/games/<slug>/id/<profile_id> 按配置查询其他育碧游戏
+        /api/status 查看育碧登录状态
+        GET/POST /api/admin/tracked, DELETE /api/admin/tracked/<game>/<profile_id> 管理定时刷新的玩家，需 Authorization: Bearer <admin_token>
     - Powered by iulx0 @ 2023
     "#
 }
 
-#[get("/div1/<name>")]
-async fn get_div1_player_stats_by_name(_limitguard: RocketGovernor<'_, RateLimitGuard>, pool: &State<Pool<Sqlite>>, name: &str) -> status::Custom<Json<Response>> {
-    let stats = get_div1_player_stats(pool, name).await;
+#[get("/div1/<name>?<pick>")]
+async fn get_div1_player_stats_by_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, name: &str, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_div1_player_stats(pool, sessions, config, "uplay", name, pick).await;
     status::Custom(
         Status::from_code(stats.status_code).unwrap(),
         Json(stats.response),
     )
 }
 
-#[get("/div2/<name>")]
-async fn get_div2_player_stats_by_name(_limitguard: RocketGovernor<'_, RateLimitGuard>, pool: &State<Pool<Sqlite>>, name: &str) -> status::Custom<Json<Response>> {
-    let stats = get_div2_player_stats(pool, name).await;
+#[get("/div1/id/<profile_id>")]
+async fn get_div1_player_stats_by_profile_id(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, profile_id: &str) -> status::Custom<Json<Response>> {
+    let stats = get_div1_player_stats_by_id(pool, sessions, config, profile_id).await;
     status::Custom(
         Status::from_code(stats.status_code).unwrap(),
         Json(stats.response),
     )
 }
 
+#[get("/div1/<platform>/<name>?<pick>", rank = 2)]
+async fn get_div1_player_stats_by_platform_and_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, platform: &str, name: &str, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_div1_player_stats(pool, sessions, config, platform, name, pick).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/div2/<name>?<pick>&<source>")]
+async fn get_div2_player_stats_by_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, name: &str, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_div2_player_stats(pool, sessions, config, "uplay", name, pick, source).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/div2/id/<profile_id>?<source>")]
+async fn get_div2_player_stats_by_profile_id(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, profile_id: &str, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_div2_player_stats_by_id(pool, sessions, config, profile_id, source).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/div2/<platform>/<name>?<pick>&<source>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_player_stats_by_platform_and_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, platform: &str, name: &str, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_div2_player_stats(pool, sessions, config, platform, name, pick, source).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/div1/<name>/progress?<since>&<pick>", rank = 1)]
+async fn get_div1_player_progress_by_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, name: &str, since: Option<&str>, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress(pool, sessions, config, Division::One, "uplay", name, pick, since, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/id/<profile_id>/progress?<since>")]
+async fn get_div1_player_progress_by_profile_id(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, profile_id: &str, since: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress_by_id(pool, sessions, config, Division::One, profile_id, since, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/<platform>/<name>/progress?<since>&<pick>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div1_player_progress_by_platform_and_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, platform: &str, name: &str, since: Option<&str>, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress(pool, sessions, config, Division::One, platform, name, pick, since, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/<name>/progress?<since>&<pick>&<source>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_player_progress_by_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, name: &str, since: Option<&str>, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress(pool, sessions, config, Division::Two, "uplay", name, pick, since, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/id/<profile_id>/progress?<since>&<source>")]
+async fn get_div2_player_progress_by_profile_id(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, profile_id: &str, since: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress_by_id(pool, sessions, config, Division::Two, profile_id, since, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/<platform>/<name>/progress?<since>&<pick>&<source>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_player_progress_by_platform_and_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, platform: &str, name: &str, since: Option<&str>, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_progress(pool, sessions, config, Division::Two, platform, name, pick, since, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/<name>/history?<field>&<from>&<to>&<bucket>&<pick>", rank = 1)]
+async fn get_div1_player_history_by_name(pool: &State<Pool<Sqlite>>, name: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history(pool, Division::One, "uplay", name, pick, &HistoryQuery { field, from, to, bucket }, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/id/<profile_id>/history?<field>&<from>&<to>&<bucket>")]
+async fn get_div1_player_history_by_profile_id(pool: &State<Pool<Sqlite>>, profile_id: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history_by_id(pool, Division::One, profile_id, &HistoryQuery { field, from, to, bucket }, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/<platform>/<name>/history?<field>&<from>&<to>&<bucket>&<pick>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div1_player_history_by_platform_and_name(pool: &State<Pool<Sqlite>>, platform: &str, name: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history(pool, Division::One, platform, name, pick, &HistoryQuery { field, from, to, bucket }, None).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/<name>/history?<field>&<from>&<to>&<bucket>&<pick>&<source>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_player_history_by_name(pool: &State<Pool<Sqlite>>, name: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history(pool, Division::Two, "uplay", name, pick, &HistoryQuery { field, from, to, bucket }, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/id/<profile_id>/history?<field>&<from>&<to>&<bucket>&<source>")]
+async fn get_div2_player_history_by_profile_id(pool: &State<Pool<Sqlite>>, profile_id: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history_by_id(pool, Division::Two, profile_id, &HistoryQuery { field, from, to, bucket }, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/<platform>/<name>/history?<field>&<from>&<to>&<bucket>&<pick>&<source>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_player_history_by_platform_and_name(pool: &State<Pool<Sqlite>>, platform: &str, name: &str, field: Option<&str>, from: Option<&str>, to: Option<&str>, bucket: Option<&str>, pick: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_player_history(pool, Division::Two, platform, name, pick, &HistoryQuery { field, from, to, bucket }, source).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/leaderboard?<stat>&<limit>&<offset>&<min_level>&<max_age>&<platform>")]
+async fn get_div1_leaderboard(pool: &State<Pool<Sqlite>>, stat: Option<&str>, limit: Option<i64>, offset: Option<i64>, min_level: Option<f64>, max_age: Option<&str>, platform: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_leaderboard(pool, Division::One, &LeaderboardQuery { stat, limit, offset, min_level, max_age, platform, source: None }).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div2/leaderboard?<stat>&<limit>&<offset>&<min_level>&<max_age>&<platform>&<source>")]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_leaderboard(pool: &State<Pool<Sqlite>>, stat: Option<&str>, limit: Option<i64>, offset: Option<i64>, min_level: Option<f64>, max_age: Option<&str>, platform: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = get_leaderboard(pool, Division::Two, &LeaderboardQuery { stat, limit, offset, min_level, max_age, platform, source }).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+/// 429 for a request costing more of the client's lookup budget than is left.
+fn rate_limited(wait: u64) -> ResponseWithStatus {
+    ResponseWithStatus::error(
+        ApiStatus::TooManyRequests,
+        util::message::ERROR_CODE_RATE_LIMITED,
+        format!("{}, retry in {} seconds", util::message::MESSAGE_TOO_MANY_REQUESTS, wait),
+    )
+}
+
+/// One lookup per compared player from the client's lookup budget, charged
+/// once the request is known to be valid.
+#[allow(clippy::too_many_arguments)]
+async fn compare_players(
+    limiter: &WeightedRateLimiter,
+    ip: IpAddr,
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    names: Option<&str>,
+    platform: Option<&str>,
+    source: Option<&str>,
+) -> status::Custom<Json<Response>> {
+    let r = match compare_size(names, platform, source).map(|players| limiter.check(ip, players as u32)) {
+        Ok(Ok(())) => get_compare(pool, sessions, config, division, names, platform, source).await,
+        Ok(Err(wait)) => rate_limited(wait),
+        Err(e) => ResponseWithStatus::from(&e),
+    };
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/div1/compare?<names>&<platform>")]
+async fn get_div1_compare(limiter: &State<WeightedRateLimiter>, ip: IpAddr, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, names: Option<&str>, platform: Option<&str>) -> status::Custom<Json<Response>> {
+    compare_players(limiter, ip, pool, sessions, config, Division::One, names, platform, None).await
+}
+
+#[get("/div2/compare?<names>&<platform>&<source>")]
+#[allow(clippy::too_many_arguments)]
+async fn get_div2_compare(limiter: &State<WeightedRateLimiter>, ip: IpAddr, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, names: Option<&str>, platform: Option<&str>, source: Option<&str>) -> status::Custom<Json<Response>> {
+    compare_players(limiter, ip, pool, sessions, config, Division::Two, names, platform, source).await
+}
+
+/// One lookup per player from the client's lookup budget, charged once the
+/// batch is known to be valid and answered with 429 when exhausted.
+async fn batch_player_stats(
+    limiter: &WeightedRateLimiter,
+    ip: IpAddr,
+    pool: &Pool<Sqlite>,
+    sessions: &UbiSessionPool,
+    config: &AppConfig,
+    division: Division,
+    batch: &BatchRequest,
+) -> status::Custom<Json<Response>> {
+    let stats = match batch_size(config, batch).map(|players| limiter.check(ip, players as u32)) {
+        Ok(Ok(())) => get_batch_player_stats(pool, sessions, config, division, batch).await,
+        Ok(Err(wait)) => rate_limited(wait),
+        Err(e) => ResponseWithStatus::from(&e),
+    };
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[post("/div1/batch", data = "<batch>")]
+async fn get_div1_batch_player_stats(limiter: &State<WeightedRateLimiter>, ip: IpAddr, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, batch: Json<BatchRequest>) -> status::Custom<Json<Response>> {
+    batch_player_stats(limiter, ip, pool, sessions, config, Division::One, &batch).await
+}
+
+#[post("/div2/batch", data = "<batch>")]
+async fn get_div2_batch_player_stats(limiter: &State<WeightedRateLimiter>, ip: IpAddr, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, batch: Json<BatchRequest>) -> status::Custom<Json<Response>> {
+    batch_player_stats(limiter, ip, pool, sessions, config, Division::Two, &batch).await
+}
+
+#[get("/admin/tracked")]
+async fn get_tracked_players(_admin: AdminGuard, pool: &State<Pool<Sqlite>>) -> status::Custom<Json<Response>> {
+    let r = list_tracked_players(pool).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[post("/admin/tracked", data = "<request>")]
+async fn add_tracked_player(_admin: AdminGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, request: Json<TrackRequest>) -> status::Custom<Json<Response>> {
+    let r = track_player(pool, sessions, &request).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[delete("/admin/tracked/<game>/<profile_id>")]
+async fn remove_tracked_player(_admin: AdminGuard, pool: &State<Pool<Sqlite>>, game: &str, profile_id: &str) -> status::Custom<Json<Response>> {
+    let r = untrack_player(pool, game, profile_id).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/games")]
+async fn get_games(config: &State<AppConfig>) -> status::Custom<Json<Response>> {
+    let r = list_games(config);
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/games/<slug>/<name>?<pick>")]
+async fn get_game_player_stats_by_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, slug: &str, name: &str, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_game_player_stats(pool, sessions, config, slug, "uplay", name, pick).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/games/<slug>/id/<profile_id>")]
+async fn get_game_player_stats_by_profile_id(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, slug: &str, profile_id: &str) -> status::Custom<Json<Response>> {
+    let stats = get_game_player_stats_by_id(pool, sessions, config, slug, profile_id).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/games/<slug>/<platform>/<name>?<pick>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn get_game_player_stats_by_platform_and_name(_limitguard: LookupGuard, pool: &State<Pool<Sqlite>>, sessions: &State<UbiSessionPool>, config: &State<AppConfig>, slug: &str, platform: &str, name: &str, pick: Option<&str>) -> status::Custom<Json<Response>> {
+    let stats = get_game_player_stats(pool, sessions, config, slug, platform, name, pick).await;
+    status::Custom(
+        Status::from_code(stats.status_code).unwrap(),
+        Json(stats.response),
+    )
+}
+
+#[get("/players/search?<q>&<limit>&<platform>")]
+async fn search_known_players(pool: &State<Pool<Sqlite>>, q: Option<&str>, limit: Option<i64>, platform: Option<&str>) -> status::Custom<Json<Response>> {
+    let r = search_players(pool, q.unwrap_or(""), platform, limit).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/players/<profile_id>/names")]
+async fn get_player_name_timeline(pool: &State<Pool<Sqlite>>, profile_id: &str) -> status::Custom<Json<Response>> {
+    let r = get_name_timeline(pool, profile_id).await;
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+#[get("/status")]
+async fn get_session_status(sessions: &State<UbiSessionPool>) -> status::Custom<Json<Response>> {
+    let r = ResponseWithStatus::new(
+        ApiStatus::Ok,
+        util::message::MESSAGE_SESSION_STATUS.to_string(),
+        Some(json!({
+            "upstream_available": sessions.is_available().await,
+            "accounts": sessions.status().await,
+        })),
+    );
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
 #[rocket::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
-    let pool = SqlitePool::connect(std::env::var("DATABASE_URL").expect("DATABASE_URL must be set").as_str())
+    let figment = AppConfig::figment();
+    let config = AppConfig::load(&figment)?;
+
+    let pool = SqlitePool::connect(config.database_url.as_str())
         .await
         .expect("Couldn't connect to sqlite database");
 
-    let _ = login_ubi().await?;
     sqlx::migrate!()
         .run(&pool)
         .await
         .expect("Couldn't migrate the database tables");
+    db::protect_database_file(&config.database_url)?;
+
+    middleware::governor::set_quota_per_minute(config.rate_limit_per_minute);
+    let sessions = UbiSessionPool::new(pool.clone(), &config);
+    if let Err(e) = sessions.ensure_valid().await {
+        println!("Starting in degraded mode, only stored data is served until login succeeds: {}", e);
+    }
+    sessions.spawn_refreshers(Duration::from_secs(config.ubi_refresh_margin_secs));
+    api::tracked::spawn_scheduler(pool.clone(), sessions.clone(), config.clone());
 
-    let _rocket = rocket::build()
+    let _rocket = rocket::custom(figment)
         .mount(
             "/api", 
-            routes![get_div1_player_stats_by_name, get_div2_player_stats_by_name]
+            routes![
+                get_div1_player_stats_by_name,
+                get_div1_player_stats_by_platform_and_name,
+                get_div1_player_stats_by_profile_id,
+                get_div2_player_stats_by_name,
+                get_div2_player_stats_by_platform_and_name,
+                get_div2_player_stats_by_profile_id,
+                get_div1_player_progress_by_name,
+                get_div1_player_progress_by_platform_and_name,
+                get_div1_player_progress_by_profile_id,
+                get_div2_player_progress_by_name,
+                get_div2_player_progress_by_platform_and_name,
+                get_div2_player_progress_by_profile_id,
+                get_div1_player_history_by_name,
+                get_div1_player_history_by_platform_and_name,
+                get_div1_player_history_by_profile_id,
+                get_div2_player_history_by_name,
+                get_div2_player_history_by_platform_and_name,
+                get_div2_player_history_by_profile_id,
+                get_div1_leaderboard,
+                get_div2_leaderboard,
+                get_div1_compare,
+                get_div2_compare,
+                get_div1_batch_player_stats,
+                get_div2_batch_player_stats,
+                get_games,
+                get_game_player_stats_by_name,
+                get_game_player_stats_by_platform_and_name,
+                get_game_player_stats_by_profile_id,
+                search_known_players,
+                get_player_name_timeline,
+                get_session_status,
+                get_tracked_players,
+                add_tracked_player,
+                remove_tracked_player,
+            ]
         )
         .mount("/", routes![index])
         .register(
             "/", 
-            catchers![not_found, exceed_rate_limit, internal_server_error]
+            catchers![bad_request, unauthorized, forbidden, not_found, unprocessable_entity, exceed_rate_limit, internal_server_error]
         )
         .manage(pool)
+        .manage(sessions)
+        .manage(WeightedRateLimiter::per_minute(config.lookup_budget_per_minute))
+        .manage(config)
         .attach(Cors)
         .launch()
         .await?;
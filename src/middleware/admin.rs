@@ -0,0 +1,34 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::config::AppConfig;
+
+/// Lets a request through when it carries `Authorization: Bearer <admin_token>`.
+/// Without a configured token every admin route answers 403.
+pub struct AdminGuard;
+
+/// Compares in time independent of where the first difference is.
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminGuard {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let expected = request
+            .rocket()
+            .state::<AppConfig>()
+            .and_then(|c| c.admin_token.as_deref())
+            .filter(|t| !t.is_empty());
+        let expected = match expected {
+            Some(t) => t,
+            None => return Outcome::Failure((Status::Forbidden, ())),
+        };
+        match request.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer ")) {
+            Some(given) if same_token(given.trim(), expected) => Outcome::Success(AdminGuard),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
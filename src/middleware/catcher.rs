@@ -3,9 +3,28 @@ use crate::model::response::{ApiStatus, Response, ResponseWithStatus};
 use crate::util::message;
 
 
+#[catch(400)]
+pub fn bad_request() -> status::Custom<Json<Response>> {
+    let r = ResponseWithStatus::error(ApiStatus::BadRequest, message::ERROR_CODE_INVALID_REQUEST, message::MESSAGE_INVALID_REQUEST.to_string());
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+// Rocket answers 422 when a JSON body doesn't match the expected shape
+#[catch(422)]
+pub fn unprocessable_entity() -> status::Custom<Json<Response>> {
+    let r = ResponseWithStatus::error(ApiStatus::BadRequest, message::ERROR_CODE_INVALID_REQUEST, message::MESSAGE_INVALID_REQUEST.to_string());
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
 #[catch(404)]
 pub fn not_found() -> status::Custom<Json<Response>> {
-    let r = ResponseWithStatus::new(ApiStatus::NotFound, message::MESSAGE_USER_NOT_FOUND.to_string(), None);
+    let r = ResponseWithStatus::error(ApiStatus::NotFound, message::ERROR_CODE_NOT_FOUND, message::MESSAGE_USER_NOT_FOUND.to_string());
     status::Custom(
         Status::from_code(r.status_code).unwrap(),
         Json(r.response),
@@ -14,7 +33,7 @@ pub fn not_found() -> status::Custom<Json<Response>> {
 
 #[catch(429)]
 pub fn exceed_rate_limit() -> status::Custom<Json<Response>> {
-    let r = ResponseWithStatus::new(ApiStatus::TooManyRequests, message::MESSAGE_TOO_MANY_REQUESTS.to_string(), None);
+    let r = ResponseWithStatus::error(ApiStatus::TooManyRequests, message::ERROR_CODE_RATE_LIMITED, message::MESSAGE_TOO_MANY_REQUESTS.to_string());
     status::Custom(
         Status::from_code(r.status_code).unwrap(),
         Json(r.response),
@@ -23,9 +42,28 @@ pub fn exceed_rate_limit() -> status::Custom<Json<Response>> {
 
 #[catch(500)]
 pub fn internal_server_error() -> status::Custom<Json<Response>> {
-    let r = ResponseWithStatus::new(ApiStatus::InternalServerError, message::MESSAGE_INTERNAL_SERVER_ERROR.to_string(), None);
+    let r = ResponseWithStatus::error(ApiStatus::InternalServerError, message::ERROR_CODE_INTERNAL_ERROR, message::MESSAGE_INTERNAL_SERVER_ERROR.to_string());
     status::Custom(
         Status::from_code(r.status_code).unwrap(),
         Json(r.response),
     )
-}
\ No newline at end of file
+}
+
+#[catch(401)]
+pub fn unauthorized() -> status::Custom<Json<Response>> {
+    let r = ResponseWithStatus::error(ApiStatus::Unauthorized, message::ERROR_CODE_UNAUTHORIZED, message::MESSAGE_UNAUTHORIZED.to_string());
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
+
+// Only the admin routes answer 403, when no admin_token is configured
+#[catch(403)]
+pub fn forbidden() -> status::Custom<Json<Response>> {
+    let r = ResponseWithStatus::error(ApiStatus::Forbidden, message::ERROR_CODE_ADMIN_DISABLED, message::MESSAGE_ADMIN_DISABLED.to_string());
+    status::Custom(
+        Status::from_code(r.status_code).unwrap(),
+        Json(r.response),
+    )
+}
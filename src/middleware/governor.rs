@@ -1,33 +1,87 @@
-use rocket_governor::{Method, Quota, RocketGovernable, RocketGovernor};
-use rocket::{request::{self, FromRequest}, State, outcome::Outcome, Request};
+use governor::{clock::{Clock, DefaultClock}, state::keyed::DefaultKeyedStateStore, NegativeMultiDecision, RateLimiter};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_governor::{LimitError, Method, NonZeroU32, Quota, RocketGovernable, RocketGovernor};
 
-use std::ops::Deref;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// `quota` has no access to managed state, so the configured value is kept here.
+static QUOTA_PER_MINUTE: AtomicU32 = AtomicU32::new(5);
+
+pub fn set_quota_per_minute(quota: u32) {
+    QUOTA_PER_MINUTE.store(quota, Ordering::Relaxed);
+}
 
 pub struct RateLimitGuard;
 
 impl<'r> RocketGovernable<'r> for RateLimitGuard {
     fn quota(_method: Method, _route_name: &str) -> Quota {
-        Quota::per_minute(Self::nonzero(5u32))
+        Quota::per_minute(Self::nonzero(QUOTA_PER_MINUTE.load(Ordering::Relaxed)))
     }
 }
 
-struct RocketGovernorGuard<'r>(&'r RocketGovernor<'r, RateLimitGuard>);
+/// Per client budget of player lookups shared by every route that looks players
+/// up: a single lookup costs 1, a batch or comparison of 30 players costs 30.
+pub struct WeightedRateLimiter {
+    limiter: RateLimiter<IpAddr, DefaultKeyedStateStore<IpAddr>, DefaultClock>,
+    quota: Quota,
+    clock: DefaultClock,
+}
 
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for RocketGovernorGuard<'r> {
-    type Error = ();
+impl WeightedRateLimiter {
+    pub fn per_minute(quota: u32) -> Self {
+        let quota = Quota::per_minute(RateLimitGuard::nonzero(quota));
+        WeightedRateLimiter {
+            limiter: RateLimiter::keyed(quota),
+            quota,
+            clock: DefaultClock::default(),
+        }
+    }
 
-    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let rate_limiter = request.guard::<&State<RocketGovernor<RateLimitGuard>>>().await.unwrap();
-        let rate_limiter_guard = rate_limiter.inner().clone();
-        Outcome::Success(RocketGovernorGuard(rate_limiter_guard))
+    /// Takes `cost` units from the client's budget, or returns the seconds to
+    /// wait before that much is available again.
+    pub fn check(&self, ip: IpAddr, cost: u32) -> Result<(), u64> {
+        let cost = match NonZeroU32::new(cost) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        match self.limiter.check_key_n(&ip, cost) {
+            Ok(()) => Ok(()),
+            Err(NegativeMultiDecision::BatchNonConforming(_, not_until)) => {
+                Err(not_until.wait_time_from(self.clock.now()).as_secs().max(1))
+            }
+            // more than the whole budget, never going to pass
+            Err(NegativeMultiDecision::InsufficientCapacity(_)) => Err(u64::MAX),
+        }
     }
 }
 
-impl<'a> Deref for RocketGovernorGuard<'a> {
-    type Target = RocketGovernor<'a, RateLimitGuard>;
+/// Guard of the single lookup routes: the route's `RateLimitGuard` quota, then
+/// one unit of the client's lookup budget in `WeightedRateLimiter`.
+pub struct LookupGuard;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LookupGuard {
+    type Error = LimitError;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match RocketGovernor::<RateLimitGuard>::from_request(request).await {
+            Outcome::Success(_) => {}
+            Outcome::Failure(f) => return Outcome::Failure(f),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        }
+        let budget = match request.rocket().state::<WeightedRateLimiter>() {
+            Some(b) => b,
+            None => return Outcome::Failure((Status::InternalServerError, LimitError::Error)),
+        };
+        let ip = match request.client_ip() {
+            Some(ip) => ip,
+            None => return Outcome::Failure((Status::BadRequest, LimitError::MissingClientIpAddr)),
+        };
+        match budget.check(ip, 1) {
+            Ok(()) => Outcome::Success(LookupGuard),
+            Err(wait) => Outcome::Failure((Status::TooManyRequests, LimitError::GovernedRequest(wait, budget.quota))),
+        }
     }
-}
\ No newline at end of file
+}
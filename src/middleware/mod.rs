@@ -1,3 +1,4 @@
 pub mod governor;
 pub mod catcher;
-pub mod cors;
\ No newline at end of file
+pub mod cors;
+pub mod admin;
\ No newline at end of file
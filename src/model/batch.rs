@@ -0,0 +1,23 @@
+use rocket::serde::{Serialize, Deserialize};
+
+use super::response::Response;
+
+/// Body of `POST /api/div1/batch` and `POST /api/div2/batch`. Each entry is a
+/// player name or a profile id, names are looked up on `platform`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchRequest {
+    pub players: Vec<String>,
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+/// Outcome of one entry, the same body a single lookup would have returned.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchItem {
+    pub query: String,
+    pub status_code: u16,
+    #[serde(flatten)]
+    pub response: Response,
+}
@@ -0,0 +1,54 @@
+use rocket::serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::response::Response;
+use super::ubi::Platform;
+
+/// One entry of a comparison: the player it resolved to, or why it didn't.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ComparePlayerDTO {
+    pub query: String,
+    pub status_code: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Served from a recent snapshot instead of asking upstream again.
+    pub cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<String>,
+    /// The answer the single lookup route would have given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Response>,
+}
+
+/// One row of the comparison table.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct CompareFieldDTO {
+    pub field: String,
+    /// One value per entry of `players`, null where unknown.
+    pub values: Vec<Value>,
+    /// Names of the players with the highest value, several on a tie. Always
+    /// the highest: no compared field is better lower.
+    pub leaders: Vec<String>,
+    /// Each value minus the leading one, 0 for the leaders.
+    pub difference: Vec<Option<Value>>,
+    /// `difference` in percent of the leading value, null when that is 0.
+    pub difference_percent: Vec<Option<f64>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct CompareDTO {
+    pub game: String,
+    pub players: Vec<ComparePlayerDTO>,
+    pub fields: Vec<CompareFieldDTO>,
+    /// The players' `derived` metrics, compared the same way.
+    pub derived: Vec<CompareFieldDTO>,
+}
@@ -0,0 +1,162 @@
+use rocket::serde::{Serialize, Deserialize};
+
+use super::div::{D1PlayerStats, D2PlayerStats};
+
+/// `part` in percent of `whole`, rounded to 2 places. `None` when `whole` is 0,
+/// e.g. a headshot percentage of a player without kills.
+pub fn percent(part: f64, whole: f64) -> Option<f64> {
+    if whole > 0.0 {
+        Some((part / whole * 10_000.0).round() / 100.0)
+    } else {
+        None
+    }
+}
+
+/// `count` per hour of `secs` played, rounded to 2 places. `None` without playtime.
+pub fn per_hour(count: f64, secs: f64) -> Option<f64> {
+    if secs > 0.0 {
+        Some((count / (secs / 3600.0) * 100.0).round() / 100.0)
+    } else {
+        None
+    }
+}
+
+/// Snapshots stored before the seconds were kept only have the whole hours.
+fn playtime_secs(secs: u64, hours: u64) -> f64 {
+    if secs > 0 { secs as f64 } else { (hours * 3600) as f64 }
+}
+
+/// Computed from the Division 1 stats, not sent by Ubisoft.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct D1DerivedStats {
+    /// `headshot_kills / total_kills` in percent.
+    pub headshot_percent: Option<f64>,
+    /// `total_kills / playtime_secs`, per hour.
+    pub kills_per_hour: Option<f64>,
+}
+
+impl D1DerivedStats {
+    pub fn of(s: &D1PlayerStats) -> Self {
+        let kills = s.total_kills as f64;
+        D1DerivedStats {
+            headshot_percent: percent(s.headshot_kills as f64, kills),
+            kills_per_hour: per_hour(kills, playtime_secs(s.playtime_secs, s.playtime)),
+        }
+    }
+}
+
+/// Kills with each weapon class in percent of the kills with all four.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct WeaponClassShare {
+    pub shotgun: Option<f64>,
+    pub smg: Option<f64>,
+    pub pistol: Option<f64>,
+    pub rifle: Option<f64>,
+}
+
+/// Kills with each specialization in percent of the kills with all three.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct SpecializationShare {
+    pub sharpshooter: Option<f64>,
+    pub survivalist: Option<f64>,
+    pub demolitionist: Option<f64>,
+}
+
+/// Computed from the Division 2 stats, not sent by tracker.gg or Ubisoft. The
+/// statscards have no weapon class or specialization kills, so both shares
+/// are null for `source: ubi`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct D2DerivedStats {
+    /// `headshot_kills / (pvp_kills + npc_kills)` in percent.
+    pub headshot_percent: Option<f64>,
+    /// `(pvp_kills + npc_kills) / total_playtime_secs`, per hour.
+    pub kills_per_hour: Option<f64>,
+    pub weapon_class_share: WeaponClassShare,
+    pub specialization_share: SpecializationShare,
+}
+
+impl D2DerivedStats {
+    pub fn of(s: &D2PlayerStats) -> Self {
+        let kills = (s.pvp_kills + s.npc_kills) as f64;
+        let weapon_kills = [s.shotgun_kills, s.smg_kills, s.pistol_kills, s.rifle_kills];
+        let specialization_kills = [s.sharpshooter_kills, s.survivalist_kills, s.demolitionist_kills];
+        D2DerivedStats {
+            headshot_percent: percent(s.headshot_kills as f64, kills),
+            kills_per_hour: per_hour(kills, playtime_secs(s.total_playtime_secs, s.total_playtime)),
+            weapon_class_share: WeaponClassShare {
+                shotgun: share(s.shotgun_kills, &weapon_kills),
+                smg: share(s.smg_kills, &weapon_kills),
+                pistol: share(s.pistol_kills, &weapon_kills),
+                rifle: share(s.rifle_kills, &weapon_kills),
+            },
+            specialization_share: SpecializationShare {
+                sharpshooter: share(s.sharpshooter_kills, &specialization_kills),
+                survivalist: share(s.survivalist_kills, &specialization_kills),
+                demolitionist: share(s.demolitionist_kills, &specialization_kills),
+            },
+        }
+    }
+}
+
+/// `kills` in percent of the sum of `all`; `None` unless every count is known.
+fn share(kills: Option<u64>, all: &[Option<u64>]) -> Option<f64> {
+    let whole: u64 = all.iter().copied().sum::<Option<u64>>()?;
+    percent(kills? as f64, whole as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_of_nothing_is_none() {
+        assert_eq!(percent(300.0, 1200.0), Some(25.0));
+        assert_eq!(percent(1.0, 3.0), Some(33.33));
+        assert_eq!(percent(0.0, 10.0), Some(0.0));
+        assert_eq!(percent(5.0, 0.0), None);
+        assert_eq!(percent(0.0, 0.0), None);
+        assert_eq!(percent(5.0, -10.0), None);
+    }
+
+    #[test]
+    fn per_hour_without_playtime_is_none() {
+        assert_eq!(per_hour(1200.0, 36000.0), Some(120.0));
+        assert_eq!(per_hour(300.0, 5400.0), Some(200.0));
+        assert_eq!(per_hour(1.0, 7.0 * 3600.0), Some(0.14));
+        assert_eq!(per_hour(0.0, 3600.0), Some(0.0));
+        assert_eq!(per_hour(10.0, 0.0), None);
+        assert_eq!(per_hour(10.0, -3600.0), None);
+    }
+
+    #[test]
+    fn kills_per_hour_falls_back_to_whole_hours() {
+        let s = D1PlayerStats { total_kills: 1200, playtime: 10, ..Default::default() };
+        assert_eq!(D1DerivedStats::of(&s).kills_per_hour, Some(120.0));
+        let s = D1PlayerStats { total_kills: 1200, playtime: 0, playtime_secs: 1800, ..Default::default() };
+        assert_eq!(D1DerivedStats::of(&s).kills_per_hour, Some(2400.0));
+        let s = D1PlayerStats { total_kills: 1200, ..Default::default() };
+        assert_eq!(D1DerivedStats::of(&s), D1DerivedStats { headshot_percent: Some(0.0), kills_per_hour: None });
+    }
+
+    #[test]
+    fn shares_need_every_count() {
+        let s = D2PlayerStats {
+            shotgun_kills: Some(100),
+            smg_kills: Some(300),
+            pistol_kills: Some(0),
+            rifle_kills: Some(100),
+            sharpshooter_kills: Some(10),
+            survivalist_kills: None,
+            demolitionist_kills: Some(10),
+            ..Default::default()
+        };
+        let d = D2DerivedStats::of(&s);
+        assert_eq!(d.weapon_class_share, WeaponClassShare { shotgun: Some(20.0), smg: Some(60.0), pistol: Some(0.0), rifle: Some(20.0) });
+        assert_eq!(d.specialization_share, SpecializationShare::default());
+        assert_eq!(d.headshot_percent, None);
+    }
+}
@@ -1,59 +1,190 @@
 use rocket::serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(crate = "rocket::serde")]
+use super::derived::{D1DerivedStats, D2DerivedStats};
+use super::ubi::{Platform, ProfileDTO};
+
+/// Missing fields deserialize to their default, so stored snapshots (which
+/// leave out who the stats belong to) read back as stats too.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(crate = "rocket::serde", default)]
 pub struct D1PlayerStats {
     #[serde(skip_serializing)]
     pub id: String,
     pub name: String,
+    pub platform: Platform,
     pub level: u64,
     pub dz_rank: u64,
     pub ug_rank: u64,
+    /// Whole hours; `playtime_secs` is the exact value.
     pub playtime: u64,
+    pub playtime_secs: u64,
     pub main_story: String,
+    pub main_story_percent: f64,
     pub total_kills: u64,
     pub rogue_kills: u64,
     pub items_extracted: u64,
     pub skill_kills: u64,
+    pub headshot_kills: u64,
+    pub npc_kills: u64,
     pub gear_score: u64,
+    /// Statscards we don't map to a field yet, by statName.
+    pub extra: BTreeMap<String, Value>,
+    /// Filled by `with_derived`, left out of snapshots.
+    pub derived: D1DerivedStats,
     pub all_names: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(crate = "rocket::serde")]
+impl D1PlayerStats {
+    pub fn with_derived(mut self) -> Self {
+        self.derived = D1DerivedStats::of(&self);
+        self
+    }
+
+    pub fn profile(&self) -> ProfileDTO {
+        ProfileDTO { id: self.id.clone(), platform: self.platform, name: Some(self.name.clone()) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    One,
+    Two,
+}
+
+impl Division {
+    pub fn parse(s: &str) -> Option<Division> {
+        match s.to_lowercase().as_str() {
+            "div1" | "1" => Some(Division::One),
+            "div2" | "2" => Some(Division::Two),
+            _ => None,
+        }
+    }
+
+    /// `game` of the division's stats snapshots.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Division::One => "div1",
+            Division::Two => "div2",
+        }
+    }
+
+    /// Field holding the whole hours played, in both the stats and their snapshots.
+    pub fn playtime_field(&self) -> &'static str {
+        match self {
+            Division::One => "playtime",
+            Division::Two => "total_playtime",
+        }
+    }
+
+    /// Field holding the seconds played; snapshots from before it existed only
+    /// have `playtime_field`.
+    pub fn playtime_secs_field(&self) -> &'static str {
+        match self {
+            Division::One => "playtime_secs",
+            Division::Two => "total_playtime_secs",
+        }
+    }
+}
+
+/// Where The Division 2 stats come from. tracker.gg has every field but needs
+/// a browser; Ubisoft's statscards only fill a subset (see README).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Div2Source {
+    Ubi,
+    #[default]
+    Tracker,
+}
+
+impl Div2Source {
+    pub fn parse(s: &str) -> Option<Div2Source> {
+        match s.to_lowercase().as_str() {
+            "ubi" | "ubisoft" | "statscard" => Some(Div2Source::Ubi),
+            "tracker" | "tracker.gg" => Some(Div2Source::Tracker),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Div2Source::Ubi => "ubi",
+            Div2Source::Tracker => "tracker",
+        }
+    }
+}
+
+/// Missing fields deserialize to their default, like `D1PlayerStats`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(crate = "rocket::serde", default)]
 pub struct D2PlayerStats {
     #[serde(skip_serializing)]
     pub id: String,
     pub name: String,
+    pub platform: Platform,
+    pub source: Div2Source,
+    /// Whole hours; `total_playtime_secs` is the exact value.
     pub total_playtime: u64,
+    pub total_playtime_secs: u64,
     pub level: u64,
     pub pvp_kills: u64,
     pub npc_kills: u64,
     pub headshots: u64,
     pub headshot_kills: u64,
-    pub shotgun_kills: u64,
-    pub smg_kills: u64,
-    pub pistol_kills: u64,
-    pub rifle_kills: u64,
+    /// tracker.gg only: left out for `source: ubi` rather than reported as 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shotgun_kills: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smg_kills: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pistol_kills: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rifle_kills: Option<u64>,
     pub player_kills: u64,
     pub xp_total: u64,
-    pub pve_xp: u64,
-    pub pvp_xp: u64,
-    pub clan_xp: u64,
-    pub sharpshooter_kills: u64,
-    pub survivalist_kills: u64,
-    pub demolitionist_kills: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pve_xp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pvp_xp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clan_xp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sharpshooter_kills: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub survivalist_kills: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub demolitionist_kills: Option<u64>,
     pub e_credit: u64,
-    pub commendation_count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commendation_count: Option<u64>,
     pub commendation_score: u64,
     pub gear_score: u64,
     pub dz_rank: u64,
     pub dz_playtime: u64,
     pub rogues_killed: u64,
-    pub rogue_playtime: u64,
-    pub longest_rogue: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rogue_playtime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longest_rogue: Option<u64>,
     pub conflict_rank: u64,
     pub conflict_playtime: u64,
+    /// Statscards we don't map to a field yet, by statName (ubi source only).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, Value>,
+    /// Filled by `with_derived`, left out of snapshots.
+    pub derived: D2DerivedStats,
     pub all_names: Vec<String>,
 }
 
+impl D2PlayerStats {
+    pub fn with_derived(mut self) -> Self {
+        self.derived = D2DerivedStats::of(&self);
+        self
+    }
+
+    pub fn profile(&self) -> ProfileDTO {
+        ProfileDTO { id: self.id.clone(), platform: self.platform, name: Some(self.name.clone()) }
+    }
+}
+
@@ -0,0 +1,68 @@
+use rocket::serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::ubi::Platform;
+
+/// Where a statscard ends up in the response: just a field name, or a field
+/// name plus a divisor (`{ field = "playtime_hours", divide = 3600 }`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum StatField {
+    Name(String),
+    Scaled {
+        field: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        divide: Option<f64>,
+    },
+}
+
+impl StatField {
+    pub fn field(&self) -> &str {
+        match self {
+            StatField::Name(field) | StatField::Scaled { field, .. } => field,
+        }
+    }
+
+    pub fn divisor(&self) -> f64 {
+        match self {
+            StatField::Scaled { divide: Some(d), .. } => *d,
+            _ => 1.0,
+        }
+    }
+}
+
+/// A Ubisoft title served under `/api/games/<slug>/...`, read from the config.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct GameDefinition {
+    pub slug: String,
+    pub name: String,
+    pub space_id: String,
+    /// statName of the statscard -> field of the response.
+    #[serde(default)]
+    pub stats: BTreeMap<String, StatField>,
+}
+
+/// Layout of the `games_file`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct GamesFile {
+    #[serde(default)]
+    pub games: Vec<GameDefinition>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct GamePlayerStats {
+    #[serde(skip_serializing)]
+    pub id: String,
+    pub name: String,
+    pub platform: Platform,
+    pub game: String,
+    /// Mapped statscards by field; a field whose card Ubisoft didn't send is left out.
+    pub stats: BTreeMap<String, Value>,
+    /// Statscards without a mapping, by statName.
+    pub extra: BTreeMap<String, Value>,
+    pub all_names: Vec<String>,
+}
@@ -1,3 +1,9 @@
 pub mod div;
 pub mod ubi;
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod batch;
+pub mod game;
+pub mod snapshot;
+pub mod compare;
+pub mod derived;
+pub mod tracked;
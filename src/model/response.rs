@@ -8,6 +8,8 @@ use crate::util::message;
 pub struct Response {
     pub status: String,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
 }
@@ -24,11 +26,14 @@ pub struct ResponseWithStatus {
 pub enum ApiStatus {
     Ok,
     Created,
+    MultipleChoices,
     BadRequest,
     Unauthorized,
     Forbidden,
     NotFound,
     InternalServerError,
+    BadGateway,
+    ServiceUnavailable,
     TooManyRequests,
 }
 
@@ -37,11 +42,14 @@ impl ApiStatus {
         match self {
             ApiStatus::Ok => 200,
             ApiStatus::Created => 201,
+            ApiStatus::MultipleChoices => 300,
             ApiStatus::BadRequest => 400,
             ApiStatus::Unauthorized => 401,
             ApiStatus::Forbidden => 403,
             ApiStatus::NotFound => 404,
             ApiStatus::InternalServerError => 500,
+            ApiStatus::BadGateway => 502,
+            ApiStatus::ServiceUnavailable => 503,
             ApiStatus::TooManyRequests => 429,
         }
     }
@@ -52,11 +60,14 @@ impl Display for ApiStatus {
         match self {
             ApiStatus::Ok => write!(f, "{}", message::STATUS_OK),
             ApiStatus::Created => write!(f, "{}", message::STATUS_CREATED),
+            ApiStatus::MultipleChoices => write!(f, "{}", message::STATUS_MULTIPLE_CHOICES),
             ApiStatus::BadRequest => write!(f, "{}", message::STATUS_BAD_REQUEST),
             ApiStatus::Unauthorized => write!(f, "{}", message::STATUS_UNAUTHORIZED),
             ApiStatus::Forbidden => write!(f, "{}", message::STATUS_FORBIDDEN),
             ApiStatus::NotFound => write!(f, "{}", message::STATUS_NOT_FOUND),
             ApiStatus::InternalServerError => write!(f, "{}", message::STATUS_INTERNAL_SERVER_ERROR),
+            ApiStatus::BadGateway => write!(f, "{}", message::STATUS_BAD_GATEWAY),
+            ApiStatus::ServiceUnavailable => write!(f, "{}", message::STATUS_SERVICE_UNAVAILABLE),
             ApiStatus::TooManyRequests => write!(f, "{}", message::STATUS_TOO_MANY_REQUESTS),
         }
     }
@@ -69,8 +80,15 @@ impl ResponseWithStatus {
             response: Response {
                 status: status.to_string(),
                 message,
+                error_code: None,
                 data,
             }
         }
     }
+
+    pub fn error(status: ApiStatus, error_code: &str, message: String) -> Self {
+        let mut r = ResponseWithStatus::new(status, message, None);
+        r.response.error_code = Some(error_code.to_string());
+        r
+    }
 }
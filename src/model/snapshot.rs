@@ -0,0 +1,103 @@
+use rocket::serde::{Serialize, Serializer, Deserialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::ubi::Platform;
+
+/// A row of `stats_snapshots`, timestamps in RFC 3339.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct SnapshotDTO {
+    pub source: String,
+    /// The stats as stored, a JSON object.
+    pub stats: String,
+    pub fetched_at: String,
+    pub last_fetched_at: String,
+}
+
+/// What a player gained between the snapshot in effect at `since` and the latest one.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ProgressDTO {
+    pub id: String,
+    pub name: String,
+    pub platform: Platform,
+    pub game: String,
+    pub source: String,
+    pub since: String,
+    /// When the compared snapshots were fetched.
+    pub from: String,
+    pub to: String,
+    /// false when the oldest snapshot is newer than `since`, `from` is then later than asked.
+    pub complete: bool,
+    /// false when Ubisoft couldn't be reached and only stored snapshots were compared.
+    pub refreshed: bool,
+    pub playtime_hours: f64,
+    /// Change of every numeric field, unmapped statscards as `extra.<statName>`.
+    pub deltas: BTreeMap<String, Value>,
+    /// Kills, xp, headshots and extractions gained per hour played in the period.
+    pub per_playtime_hour: BTreeMap<String, f64>,
+}
+
+/// The values of one field seen within a bucket.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct HistoryPointDTO {
+    /// Start of the bucket.
+    pub t: String,
+    /// Latest value in the bucket.
+    pub value: Value,
+    pub min: Value,
+    pub max: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct HistoryDTO {
+    pub id: String,
+    pub name: String,
+    pub platform: Platform,
+    pub game: String,
+    pub source: String,
+    pub field: String,
+    pub bucket: String,
+    pub from: String,
+    pub to: String,
+    /// Only buckets with a fetch in them, oldest first.
+    pub points: Vec<HistoryPointDTO>,
+}
+
+fn whole_as_integer<S: Serializer>(v: &f64, s: S) -> Result<S::Ok, S::Error> {
+    if v.fract() == 0.0 && v.abs() < i64::MAX as f64 {
+        s.serialize_i64(*v as i64)
+    } else {
+        s.serialize_f64(*v)
+    }
+}
+
+/// A player on a leaderboard, with the stat as last fetched.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct LeaderboardEntryDTO {
+    /// Players with the same value share a rank.
+    pub rank: i64,
+    pub id: String,
+    pub platform: String,
+    pub name: String,
+    #[serde(serialize_with = "whole_as_integer")]
+    pub value: f64,
+    pub source: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct LeaderboardDTO {
+    pub game: String,
+    pub stat: String,
+    /// Players matching the filters, across all pages.
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub entries: Vec<LeaderboardEntryDTO>,
+}
@@ -0,0 +1,30 @@
+use rocket::serde::{Serialize, Deserialize};
+
+/// Body of `POST /api/admin/tracked`. `player` is a name, looked up on
+/// `platform`, or a profile id; `game` is `div1` or `div2`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TrackRequest {
+    pub player: String,
+    #[serde(default)]
+    pub platform: Option<String>,
+    pub game: String,
+    #[serde(default)]
+    pub pick: Option<String>,
+}
+
+/// A tracked player and how their latest background refresh went.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TrackedPlayerDTO {
+    pub id: String,
+    pub game: String,
+    pub name: Option<String>,
+    pub platform: Option<String>,
+    pub added_at: String,
+    /// Null until the first refresh.
+    pub last_refresh_at: Option<String>,
+    pub last_status_code: Option<i64>,
+    pub last_error_code: Option<String>,
+    pub last_message: Option<String>,
+}
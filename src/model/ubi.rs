@@ -1,17 +1,189 @@
+use chrono::{DateTime, Utc};
 use rocket::serde::{Serialize, Deserialize};
 use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+/// Platform a Ubisoft profile lives on. Profile ids and names are per
+/// platform, the same name on uplay and psn are two different players.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Platform {
+    #[default]
+    Uplay,
+    Psn,
+    Xbl,
+}
+
+impl Platform {
+    /// Accepts Ubisoft's own names plus the obvious aliases (`pc`, `ps`, `xbox`, ...).
+    pub fn parse(s: &str) -> Option<Platform> {
+        match s.to_lowercase().as_str() {
+            "uplay" | "ubi" | "pc" => Some(Platform::Uplay),
+            "psn" | "ps" | "ps4" | "ps5" | "playstation" => Some(Platform::Psn),
+            "xbl" | "xbox" | "xone" => Some(Platform::Xbl),
+            _ => None,
+        }
+    }
+
+    /// `platformType` / `Ubi-RequestedPlatformType` value on Ubisoft services,
+    /// also the platform slug tracker.gg uses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Uplay => "uplay",
+            Platform::Psn => "psn",
+            Platform::Xbl => "xbl",
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct ProfileDTO {
     pub id: String,
+    pub platform: Platform,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 }
 
+/// One entry of a Ubisoft `Statscards` response.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", rename_all = "camelCase")]
+pub struct StatCard {
+    pub stat_name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub value: Value,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+impl StatCard {
+    /// Values come as strings like `"1200"` or `"67 %"`, sometimes as numbers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match &self.value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != ',' && *c != '%')
+                .collect::<String>()
+                .parse::<f64>()
+                .ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.as_f64().map(|v| v.max(0.0) as u64).unwrap_or(0)
+    }
+
+    /// The value as a JSON number (an integer when it has no fraction), or as sent.
+    pub fn to_json(&self) -> Value {
+        self.scaled_json(1.0)
+    }
+
+    /// Like `to_json`, with numbers divided by `divisor` first (seconds to hours, ...).
+    pub fn scaled_json(&self, divisor: f64) -> Value {
+        match self.as_f64().map(|v| v / divisor) {
+            Some(v) if v.fract() == 0.0 && v.abs() < i64::MAX as f64 => Value::from(v as i64),
+            Some(v) => Value::from(v),
+            None => self.value.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct StatsDTO {
     pub profile: ProfileDTO,
-    pub stats: Vec<Value>,
-}
\ No newline at end of file
+    pub stats: Vec<StatCard>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct StoredPlayerDTO {
+    pub id: String,
+    pub platform: Platform,
+    pub all_names: Vec<String>,
+}
+
+/// One name a player has used, from the first to the latest time we saw it.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NameHistoryDTO {
+    pub name: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub sightings: i64,
+}
+
+/// Every name of a stored player, latest first.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NameTimelineDTO {
+    pub id: String,
+    pub platform: Platform,
+    pub current_name: String,
+    pub names: Vec<NameHistoryDTO>,
+}
+
+/// One of several players a name resolved to, with when they held that name
+/// (unknown for profiles we have never stored).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct CandidateDTO {
+    pub id: String,
+    pub platform: Platform,
+    pub current_name: String,
+    pub held_from: Option<String>,
+    pub held_until: Option<String>,
+}
+
+/// Stored holder of a name, see `CandidateDTO`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NameHolderDTO {
+    pub id: String,
+    pub current_name: String,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// A known player suggested for a name prefix, from stored data only.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct PlayerMatchDTO {
+    pub id: String,
+    pub platform: String,
+    pub matched_name: String,
+    pub current_name: String,
+    pub last_seen: Option<String>,
+    pub lookups: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionDTO {
+    pub ticket: String,
+    pub session_id: String,
+    pub expiration: String,
+    pub remember_me_ticket: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub next_refresh: Option<DateTime<Utc>>,
+}
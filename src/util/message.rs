@@ -1,9 +1,30 @@
-#[allow(unused)]
-
 pub static MESSAGE_USER_EXISTS: &str = "user exists";
 pub static MESSAGE_USER_NOT_FOUND: &str = "user not found";
 pub static MESSAGE_INTERNAL_SERVER_ERROR: &str = "internal server error";
 pub static MESSAGE_TOO_MANY_REQUESTS: &str = "too many requests";
+pub static MESSAGE_SESSION_STATUS: &str = "ubi session status";
+pub static MESSAGE_UPSTREAM_UNAVAILABLE: &str = "upstream unavailable, serving stored data only";
+pub static MESSAGE_BATCH_RESULTS: &str = "batch results";
+pub static MESSAGE_SEARCH_RESULTS: &str = "search results";
+pub static MESSAGE_INVALID_REQUEST: &str = "invalid request";
+pub static MESSAGE_GAMES: &str = "configured games";
+pub static MESSAGE_HISTORY: &str = "stats history";
+pub static MESSAGE_LEADERBOARD: &str = "leaderboard of players seen by this service";
+pub static MESSAGE_COMPARE: &str = "side by side stats of the players";
+pub static MESSAGE_TRACKED_PLAYERS: &str = "tracked players";
+pub static MESSAGE_TRACKED: &str = "player is tracked";
+pub static MESSAGE_UNTRACKED: &str = "player is no longer tracked";
+pub static MESSAGE_UNAUTHORIZED: &str = "missing or wrong admin token";
+pub static MESSAGE_ADMIN_DISABLED: &str = "admin routes are disabled, set admin_token to enable them";
+pub static MESSAGE_PROGRESS: &str = "progress since the requested time";
+pub static MESSAGE_PROGRESS_INCOMPLETE: &str = "history does not go back to the requested time, compared from the oldest snapshot";
+
+pub static ERROR_CODE_NOT_FOUND: &str = "NOT_FOUND";
+pub static ERROR_CODE_RATE_LIMITED: &str = "RATE_LIMITED";
+pub static ERROR_CODE_INTERNAL_ERROR: &str = "INTERNAL_ERROR";
+pub static ERROR_CODE_INVALID_REQUEST: &str = "INVALID_REQUEST";
+pub static ERROR_CODE_UNAUTHORIZED: &str = "UNAUTHORIZED";
+pub static ERROR_CODE_ADMIN_DISABLED: &str = "ADMIN_DISABLED";
 
 pub static STATUS_OK: &str = "ok";
 // pub static STATUS_ERROR: &str = "error";
@@ -12,5 +33,8 @@ pub static STATUS_INTERNAL_SERVER_ERROR: &str = "internal server error";
 pub static STATUS_FORBIDDEN: &str = "forbidden";
 pub static STATUS_NOT_FOUND: &str = "not found";
 pub static STATUS_CREATED: &str = "created";
+pub static STATUS_MULTIPLE_CHOICES: &str = "multiple choices";
 pub static STATUS_BAD_REQUEST: &str = "bad request";
 pub static STATUS_TOO_MANY_REQUESTS: &str = "too many requests";
+pub static STATUS_BAD_GATEWAY: &str = "bad gateway";
+pub static STATUS_SERVICE_UNAVAILABLE: &str = "service unavailable";
@@ -1,6 +1,6 @@
 use thirtyfour::prelude::*;
 
-pub async fn get_webdriver() -> WebDriverResult<WebDriver> {
+pub async fn get_webdriver(port: u16) -> WebDriverResult<WebDriver> {
     let mut caps = DesiredCapabilities::chrome();
 
     let _ = caps.set_disable_web_security();
@@ -12,7 +12,6 @@ pub async fn get_webdriver() -> WebDriverResult<WebDriver> {
     let _ = caps.add_chrome_arg("disable-infobars");
     let _ = caps.add_chrome_option("detach", true);
 
-    let port = std::env::var("CHROME_PORT").unwrap_or("9515".to_string());
     let driver = WebDriver::new(format!("{}{}", "http://localhost:", port).as_str(), caps).await?;
     Ok(driver)
 }
\ No newline at end of file